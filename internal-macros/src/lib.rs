@@ -1,24 +1,19 @@
-use std::sync::RwLock;
+use std::sync::OnceLock;
 
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
 
 const MAX_ENTITY_COMPONENTS_ENV: Option<&'static str> = std::option_env!("MAX_ENT_COMPS");
-static mut MAX_ENTITY_COMPONENTS: RwLock<Option<usize>> = RwLock::new(None);
+static MAX_ENTITY_COMPONENTS: OnceLock<usize> = OnceLock::new();
 
 fn max_ent_comps() -> usize {
-    unsafe {
-        let mut max_ent_comps_opt = MAX_ENTITY_COMPONENTS.write().unwrap();
-        max_ent_comps_opt.unwrap_or_else(|| {
-            let max = MAX_ENTITY_COMPONENTS_ENV.map(|v| {
-                v.parse::<usize>()
-                    .expect("MAX_ENT_COMPS should be a number")
-            }).unwrap_or(50);
-            *max_ent_comps_opt = Some(max);
-            max
-        })
-    }
+    *MAX_ENTITY_COMPONENTS.get_or_init(|| {
+        MAX_ENTITY_COMPONENTS_ENV.map(|v| {
+            v.parse::<usize>()
+                .expect("MAX_ENT_COMPS should be a number")
+        }).unwrap_or(50)
+    })
 }
 
 #[proc_macro]
@@ -40,46 +35,44 @@ pub fn gen_spawn_entity(_: TokenStream) -> TokenStream {
                 #name: #c
             }
         }).collect();
-        if generics.len() != 0 {
+        if !generics.is_empty() {
             generics.insert(0, quote! {<});
             generics.push(quote! {>});
         }
-        let new_archetype_sizes_vector_elements = chars.iter().flat_map(|generic| {
+        let new_archetype_infos_vector_elements = chars.iter().flat_map(|generic| {
             quote! {
-                (<#generic>::id(), ::std::mem::size_of::<#generic>()),
+                (<#generic>::id(), crate::arch::ComponentInfo::of::<#generic>()),
             }
         }).collect::<TokenStream2>();
         
-        let init_archetype_size = if chars.len() == 0 {
+        let init_archetype_size = if chars.is_empty() {
             quote!{}
         } else {
             quote! {
-                let sizes = {
+                let infos = {
                     let mut v = vec![
-                        #new_archetype_sizes_vector_elements
+                        #new_archetype_infos_vector_elements
                     ];
                     
                     v.sort_by(|a, b| {
                         a.0.partial_cmp(&b.0).unwrap()
                     });
                     
-                    v.iter().map(|(_, size)| *size).collect::<Vec<usize>>()
+                    v.iter().map(|(_, info)| *info).collect::<Vec<crate::arch::ComponentInfo>>()
                 };
-                self.arch_store.get_archetype_mut(id).init(&components, &sizes);
+                self.arch_store.get_archetype_mut(id).init(&components, &infos);
             }
         };
         
         fns.push(quote! {
             #[doc(hidden)]
+            #[allow(clippy::too_many_arguments)]
             pub fn #name #(#generics)* (&mut self, #(#params , )*) -> EntityId {
                 let ent_id = self.entity_store.new_id();
                 let mut components = vec![#(<#chars>::id(), )*];
                 components.sort();
                 let arch_id = match self.arch_store.get_new_entity_archetype(&components) {
                     NewEntityResult::NewArchetype(id) => {
-                        #(
-                            <#chars>::add_archetype(id);
-                        )*
                         #init_archetype_size
                         id
                     }
@@ -88,7 +81,7 @@ pub fn gen_spawn_entity(_: TokenStream) -> TokenStream {
                 let archetype = &mut self.arch_store.archetypes[arch_id as usize];
                 let arch_row = archetype.new_archrow_id(ent_id);
                 #(
-                    archetype.set_component(arch_row, #param_names);
+                    archetype.init_component(arch_row, #param_names, self.change_tick);
                 )*
                 self.entity_store.spawn_with_id(ent_id, arch_id, arch_row);
                 return ent_id;
@@ -116,20 +109,15 @@ fn itos(int: usize) -> String {
 }
 
 const MAX_QUERY_COMPONENTS_ENV: Option<&'static str> = std::option_env!("MAX_QUERY_COMPS");
-static mut MAX_QUERY_COMPONENTS: RwLock<Option<usize>> = RwLock::new(None);
+static MAX_QUERY_COMPONENTS: OnceLock<usize> = OnceLock::new();
 
 fn max_query_comps() -> usize {
-    unsafe {
-        let mut max_ent_comps_opt = MAX_QUERY_COMPONENTS.write().unwrap();
-        max_ent_comps_opt.unwrap_or_else(|| {
-            let max = MAX_QUERY_COMPONENTS_ENV.map(|v| {
-                v.parse::<usize>()
-                    .expect("MAX_QUERY_COMPS environment variable should be a number")
-            }).unwrap_or(15);
-            *max_ent_comps_opt = Some(max);
-            max
-        })
-    }
+    *MAX_QUERY_COMPONENTS.get_or_init(|| {
+        MAX_QUERY_COMPONENTS_ENV.map(|v| {
+            v.parse::<usize>()
+                .expect("MAX_QUERY_COMPS environment variable should be a number")
+        }).unwrap_or(15)
+    })
 }
 
 mod query_gen;
@@ -13,91 +13,201 @@ pub(crate) fn query_impl(max_query_comps: usize) -> TokenStream2 {
             _func_name_query_mut_ptr,
             _func_name_query_mut_ids,
         ) = query_names(i);
+        let (
+            func_name_query_filtered,
+            func_name_query_filtered_id,
+            func_name_query_mut_filtered,
+            func_name_query_mut_filtered_id,
+        ) = filtered_query_names(i);
+        let (
+            func_name_query_unchecked,
+            func_name_query_unchecked_id,
+            func_name_query_unchecked_filtered,
+            func_name_query_unchecked_filtered_id,
+        ) = unchecked_query_names(i);
         
         // Vec<{A}, {B}, ...>
         let generic_names: Vec<syn::Ident> = (0..i).map(|i| syn::Ident::new(&crate::itos(i).to_string(), proc_macro2::Span::call_site())).collect();
         
-        // Vec<{A: Component + 'static}, {B: Component + 'static}, ...>
-        let generics: Vec<_> = generics(&generic_names, i);
+        // Vec<{A: QueryComponent + 'static}, {B: QueryComponent + 'static}, ...>
+        let generics_mut: Vec<_> = generics(&generic_names, i, quote! { crate::QueryComponent });
+        // Vec<{A: ReadOnlyQueryComponent + 'static}, {B: ReadOnlyQueryComponent + 'static}, ...>
+        let generics: Vec<_> = generics(&generic_names, i, quote! { crate::ReadOnlyQueryComponent });
+        
+        let all_rows = quote! { ::std::ops::Range<crate::arch::ArchRowId> };
+        let filtered_rows = quote! { ::std::vec::IntoIter<crate::arch::ArchRowId> };
         
         let (
-            // impl ::std::iter::Iterator<Item= (&'a A, &'a B, ...)> + 'a
+            // crate::QueryIter<'a, impl crate::query::FetchRows<'a, R, Item = (A::Item<'a>, B::Item<'a>, ...)> + 'a, R>
             query_return_type,
             query_return_type_id,
-            // impl ::std::iter::Iterator<Item= (&'a mut A, &'a mut B, ...)> + 'a
+            // crate::QueryIter<'a, impl crate::query::FetchRows<'a, R, Item = (A::ItemMut<'a>, B::ItemMut<'a>, ...)> + 'a, R>
             query_return_type_mut,
             query_return_type_mut_id,
-        ) = return_types(&generic_names);
+        ) = return_types(&generic_names, &all_rows);
+        let (
+            query_return_type_filtered,
+            query_return_type_filtered_id,
+            query_return_type_mut_filtered,
+            query_return_type_mut_filtered_id,
+        ) = return_types(&generic_names, &filtered_rows);
         
         // Implementation //
-        // Vec<{let archetypes_a = A::get_archetypes();}, ...>
-        let archetypes_defs: Vec<_> = archetypes_defs(&generic_names);
-        
-        let filter_iterator = filter_iterator(&generic_names, i);
-        
-        let zip_reg = zip(&generic_names, GetComponentsType::Regular, false);
-        let zip_reg_id = zip(&generic_names, GetComponentsType::Regular, true);
-        let zip_mut = zip(&generic_names, GetComponentsType::Mut, false);
-        let zip_mut_id = zip(&generic_names, GetComponentsType::Mut, true);
+        // the archetypes containing all required (non-optional) components and
+        // their rows, collected so that they don't borrow `self`
+        let archetypes = quote! {
+            let archetypes: Vec<(&'a crate::arch::Archetype, #all_rows)> = self.arch_store
+                .get_archetypes_with(&[#(<#generic_names as crate::QueryComponent>::required(),)*])
+                .into_iter()
+                .map(|arch_id| {
+                    let archetype = self.arch_store.get_archetype(arch_id);
+                    (archetype, archetype.get_arch_rows())
+                })
+                .collect();
+        };
         
         let end_map_reg = end_map(i);
         let end_map_ids = end_map(i + 1);
         
+        // the items of rows of an archetype, the iterator is created for every
+        // archetype and, by `par_for_each`, for every chunk of rows
+        let fetch = |ty: GetComponentsType, query_ids: bool, rows: &TokenStream2| {
+            let zip = zip(&generic_names, ty, query_ids);
+            let end_map = if query_ids { &end_map_ids } else { &end_map_reg };
+            quote! {
+                move |archetype: &'a crate::arch::Archetype, rows: #rows| #zip #end_map
+            }
+        };
+        let fetch_reg = fetch(GetComponentsType::Regular, false, &all_rows);
+        let fetch_reg_id = fetch(GetComponentsType::Regular, true, &all_rows);
+        let fetch_mut = fetch(GetComponentsType::Mut, false, &all_rows);
+        let fetch_mut_id = fetch(GetComponentsType::Mut, true, &all_rows);
+        
+        let assert_unique = quote! {
+            crate::query::assert_unique_components(&[#((
+                <#generic_names as crate::QueryComponent>::component_id(),
+                <#generic_names as crate::QueryComponent>::writes()
+            ),)*]);
+        };
+        
+        // Filtered //
+        let filtered_archetypes = quote! {
+            // rows are collected up front, so that the filters don't need to outlive the query
+            let archetypes: Vec<(&'a crate::arch::Archetype, #filtered_rows)> = self.arch_store
+                .get_archetypes_with(&[#(<#generic_names as crate::QueryComponent>::required(),)*])
+                .into_iter()
+                .map(|arch_id| self.arch_store.get_archetype(arch_id))
+                .filter(|archetype| crate::filter::matches_archetype(filters, archetype))
+                .map(|archetype| {
+                    let rows = crate::filter::filter_rows(filters, archetype, &self.entity_store, last_change_tick);
+                    (archetype, rows.into_iter())
+                })
+                .collect();
+        };
+        
+        let fetch_reg_filtered = fetch(GetComponentsType::Regular, false, &filtered_rows);
+        let fetch_reg_filtered_id = fetch(GetComponentsType::Regular, true, &filtered_rows);
+        let fetch_mut_filtered = fetch(GetComponentsType::Mut, false, &filtered_rows);
+        let fetch_mut_filtered_id = fetch(GetComponentsType::Mut, true, &filtered_rows);
+        
         quote! {
             #[doc(hidden)]
             pub fn #func_name_query<'a, #(#generics,)*>(&'a self) -> #query_return_type {
-                #(#archetypes_defs)*
-                
-                #filter_iterator
-                    .flat_map(|arch_id| {
-                        let archetype = self.arch_store.get_archetype(arch_id);
-                        // let entities: Vec<crate::arch::ArchRowId> = archetype.get_arch_rows(&self.entity_store).collect();
-                        
-                        #zip_reg
-                    })
-                    #end_map_reg
+                #archetypes
+                crate::QueryIter::new(archetypes, #fetch_reg)
             }
             
             #[doc(hidden)]
             pub fn #func_name_query_id<'a, #(#generics,)*>(&'a self) -> #query_return_type_id {
-                #(#archetypes_defs)*
+                #archetypes
+                crate::QueryIter::new(archetypes, #fetch_reg_id)
+            }
+            
+            #[doc(hidden)]
+            pub fn #func_name_query_mut<'a, #(#generics_mut,)*>(&'a mut self) -> #query_return_type_mut {
+                // the world is borrowed mutably, so the components can't be aliased
+                unsafe { self.#func_name_query_unchecked::<#(#generic_names,)*>() }
+            }
+            
+            #[doc(hidden)]
+            pub fn #func_name_query_mut_id<'a, #(#generics_mut,)*>(&'a mut self) -> #query_return_type_mut_id {
+                unsafe { self.#func_name_query_unchecked_id::<#(#generic_names,)*>() }
+            }
+            
+            /// # Safety
+            /// No other references to the components that are written to may exist
+            /// while the query is in use
+            #[doc(hidden)]
+            pub unsafe fn #func_name_query_unchecked<'a, #(#generics_mut,)*>(&'a self) -> #query_return_type_mut {
+                #assert_unique
                 
-                #filter_iterator
-                    .flat_map(|arch_id| {
-                        let archetype = self.arch_store.get_archetype(arch_id);
-                        let entities: Vec<crate::arch::ArchRowId> = archetype.get_arch_rows().collect();
-                    
-                        #zip_reg_id
-                    })
-                    #end_map_ids
+                let change_tick = self.change_tick;
+                #archetypes
+                crate::QueryIter::new(archetypes, #fetch_mut)
+            }
+            
+            /// # Safety
+            /// No other references to the components that are written to may exist
+            /// while the query is in use
+            #[doc(hidden)]
+            pub unsafe fn #func_name_query_unchecked_id<'a, #(#generics_mut,)*>(&'a self) -> #query_return_type_mut_id {
+                #assert_unique
+                
+                let change_tick = self.change_tick;
+                #archetypes
+                crate::QueryIter::new(archetypes, #fetch_mut_id)
+            }
+            
+            #[doc(hidden)]
+            pub fn #func_name_query_filtered<'a, #(#generics,)*>(&'a self, filters: &[crate::Filter]) -> #query_return_type_filtered {
+                let last_change_tick = self.last_change_tick;
+                #filtered_archetypes
+                crate::QueryIter::new(archetypes, #fetch_reg_filtered)
+            }
+            
+            #[doc(hidden)]
+            pub fn #func_name_query_filtered_id<'a, #(#generics,)*>(&'a self, filters: &[crate::Filter]) -> #query_return_type_filtered_id {
+                let last_change_tick = self.last_change_tick;
+                #filtered_archetypes
+                crate::QueryIter::new(archetypes, #fetch_reg_filtered_id)
+            }
+            
+            #[doc(hidden)]
+            pub fn #func_name_query_mut_filtered<'a, #(#generics_mut,)*>(&'a mut self, filters: &[crate::Filter]) -> #query_return_type_mut_filtered {
+                unsafe { self.#func_name_query_unchecked_filtered::<#(#generic_names,)*>(filters, self.last_change_tick) }
+            }
+            
+            #[doc(hidden)]
+            pub fn #func_name_query_mut_filtered_id<'a, #(#generics_mut,)*>(&'a mut self, filters: &[crate::Filter]) -> #query_return_type_mut_filtered_id {
+                unsafe { self.#func_name_query_unchecked_filtered_id::<#(#generic_names,)*>(filters, self.last_change_tick) }
             }
             
+            /// Changes made after `last_change_tick` are reported by `Added` and `Changed` filters
+            ///
+            /// # Safety
+            /// No other references to the components that are written to may exist
+            /// while the query is in use
             #[doc(hidden)]
-            pub fn #func_name_query_mut<'a, #(#generics,)*>(&'a mut self) -> #query_return_type_mut {
-                #(#archetypes_defs)*
+            pub unsafe fn #func_name_query_unchecked_filtered<'a, #(#generics_mut,)*>(&'a self, filters: &[crate::Filter], last_change_tick: u32) -> #query_return_type_mut_filtered {
+                #assert_unique
                 
-                #filter_iterator
-                    .flat_map(|arch_id| {
-                        let archetype: *mut crate::arch::Archetype = self.arch_store.get_archetype_mut(arch_id);
-                        let entities: Vec<crate::arch::ArchRowId> = unsafe { (*archetype).get_arch_rows().collect() };
-                        
-                        #zip_mut
-                    })
-                    #end_map_reg
+                let change_tick = self.change_tick;
+                #filtered_archetypes
+                crate::QueryIter::new(archetypes, #fetch_mut_filtered)
             }
             
+            /// Changes made after `last_change_tick` are reported by `Added` and `Changed` filters
+            ///
+            /// # Safety
+            /// No other references to the components that are written to may exist
+            /// while the query is in use
             #[doc(hidden)]
-            pub fn #func_name_query_mut_id<'a, #(#generics,)*>(&'a mut self) -> #query_return_type_mut_id {
-                #(#archetypes_defs)*
+            pub unsafe fn #func_name_query_unchecked_filtered_id<'a, #(#generics_mut,)*>(&'a self, filters: &[crate::Filter], last_change_tick: u32) -> #query_return_type_mut_filtered_id {
+                #assert_unique
                 
-                #filter_iterator
-                    .flat_map(|arch_id| {
-                        let archetype: *mut crate::arch::Archetype = self.arch_store.get_archetype_mut(arch_id);
-                        let entities: Vec<crate::arch::ArchRowId> = unsafe { (*archetype).get_arch_rows().collect() };
-                        
-                        #zip_mut_id
-                    })
-                    #end_map_ids
+                let change_tick = self.change_tick;
+                #filtered_archetypes
+                crate::QueryIter::new(archetypes, #fetch_mut_filtered_id)
             }
         }
     }).collect();
@@ -116,106 +226,70 @@ fn query_names(i: usize) -> (syn::Ident, syn::Ident, syn::Ident, syn::Ident, syn
     )
 }
 
-fn generics(generic_names: &Vec<syn::Ident>, i: usize) -> Vec<TokenStream2> {
+fn filtered_query_names(i: usize) -> (syn::Ident, syn::Ident, syn::Ident, syn::Ident) {
+    (
+        syn::Ident::new(&format!("query_filtered{i}"), proc_macro2::Span::call_site()),
+        syn::Ident::new(&format!("query_filtered_ids{i}"), proc_macro2::Span::call_site()),
+        syn::Ident::new(&format!("query_mut_filtered{i}"), proc_macro2::Span::call_site()),
+        syn::Ident::new(&format!("query_mut_filtered_ids{i}"), proc_macro2::Span::call_site()),
+    )
+}
+
+fn unchecked_query_names(i: usize) -> (syn::Ident, syn::Ident, syn::Ident, syn::Ident) {
+    (
+        syn::Ident::new(&format!("query_mut_unchecked{i}"), proc_macro2::Span::call_site()),
+        syn::Ident::new(&format!("query_mut_unchecked_ids{i}"), proc_macro2::Span::call_site()),
+        syn::Ident::new(&format!("query_mut_unchecked_filtered{i}"), proc_macro2::Span::call_site()),
+        syn::Ident::new(&format!("query_mut_unchecked_filtered_ids{i}"), proc_macro2::Span::call_site()),
+    )
+}
+
+fn generics(generic_names: &[syn::Ident], i: usize, bound: TokenStream2) -> Vec<TokenStream2> {
     (0..i).map(|i| {
         let generic_name = &generic_names[i];
-        // A: Component + 'static
+        // A: QueryComponent + 'static
         quote! {
-            #generic_name: Component + 'static
+            #generic_name: #bound + 'static
         }
     }).collect()
 }
 
-fn return_types(generic_names: &Vec<syn::Ident>) -> (TokenStream2, TokenStream2, TokenStream2, TokenStream2) {
+fn return_types(generic_names: &[syn::Ident], rows: &TokenStream2) -> (TokenStream2, TokenStream2, TokenStream2, TokenStream2) {
+    let query_iter = |item: TokenStream2| quote! {
+        crate::QueryIter<'a, impl crate::query::FetchRows<'a, #rows, Item = #item> + 'a, #rows>
+    };
     if generic_names.len() == 1 {
         let generic_name = &generic_names[0];
         (
-            quote! {
-                impl ::std::iter::Iterator<Item = &'a #generic_name> + 'a
-            },
-            quote! {
-                impl ::std::iter::Iterator<Item = (crate::EntityId, &'a #generic_name)> + 'a
-            },
-            quote! {
-                impl ::std::iter::Iterator<Item = &'a mut #generic_name> + 'a
-            },
-            quote! {
-                impl ::std::iter::Iterator<Item = (crate::EntityId, &'a mut #generic_name)> + 'a
-            },
+            query_iter(quote! { #generic_name::Item<'a> }),
+            query_iter(quote! { (crate::EntityId, #generic_name::Item<'a>) }),
+            query_iter(quote! { #generic_name::ItemMut<'a> }),
+            query_iter(quote! { (crate::EntityId, #generic_name::ItemMut<'a>) }),
         )
     } else {
         (
-            quote! {
-                impl ::std::iter::Iterator<Item = (#(&'a #generic_names,)*)> + 'a
-            },
-            quote! {
-                impl ::std::iter::Iterator<Item = (crate::EntityId, #(&'a #generic_names,)*)> + 'a
-            },
-            quote! {
-                impl ::std::iter::Iterator<Item = (#(&'a mut #generic_names,)*)> + 'a
-            },
-            quote! {
-                impl ::std::iter::Iterator<Item = (crate::EntityId, #(&'a mut #generic_names,)*)> + 'a
-            },
+            query_iter(quote! { (#(#generic_names::Item<'a>,)*) }),
+            query_iter(quote! { (crate::EntityId, #(#generic_names::Item<'a>,)*) }),
+            query_iter(quote! { (#(#generic_names::ItemMut<'a>,)*) }),
+            query_iter(quote! { (crate::EntityId, #(#generic_names::ItemMut<'a>,)*) }),
         )
     }
 }
 
-fn archetypes_defs(generic_names: &Vec<syn::Ident>) -> Vec<TokenStream2> {
-    generic_names.iter()
-        .map(|generic_name| {
-            let var_name = syn::Ident::new(&format!("archetypes_{}", &generic_name.to_string().to_lowercase()), proc_macro2::Span::call_site());
-            quote! {
-                let #var_name = <#generic_name>::get_archetypes();
-            }
-        }).collect()
-}
-
-fn filter_iterator(generic_names: &Vec<syn::Ident>, i: usize) -> TokenStream2 {
-    if generic_names.len() == 1 {
-        quote! {
-            archetypes_a.clone().into_iter()
-        }
-    } else {
-        let filters: Vec<TokenStream2> = (1..i)
-            .map(|i| {
-                let name = syn::Ident::new(&format!("archetypes_{}", &generic_names[i].to_string().to_lowercase()), proc_macro2::Span::call_site());
-                
-                quote! {
-                    .filter(move |elem| #name.contains(elem))
-                }
-            }).collect();
-        
-        quote! {
-            archetypes_a.clone().into_iter()
-                #(#filters)*
-        }
-    }
-}
-
-fn zip(generic_names: &Vec<syn::Ident>, ty: GetComponentsType, query_ids: bool) -> TokenStream2 {
-    let archetype = match ty {
-        GetComponentsType::Regular => quote! { archetype },
-        GetComponentsType::Mut => quote! { (*archetype) },
-    };
-    
-    let id_iter = if let GetComponentsType::Mut = ty {
-        quote! {
-            unsafe {
-                #archetype.get_entity_ids()
-            }
-        }
-    } else {
-        quote! {
-            #archetype.get_entity_ids()
-        }
+/// Zips the items of each component for the `rows` of `archetype`
+fn zip(generic_names: &[syn::Ident], ty: GetComponentsType, query_ids: bool) -> TokenStream2 {
+    let id_iter = quote! { archetype.get_entity_ids_of(rows.clone()) };
+    let arch_rows = quote! { rows.clone() };
+    let change_tick = match ty {
+        GetComponentsType::Regular => quote! {},
+        GetComponentsType::Mut => quote! { , change_tick },
     };
 
     if generic_names.len() == 1 {
         let generic_name = &generic_names[0];
         let func_name = match ty {
-            GetComponentsType::Regular => quote! { get_all_components },
-            GetComponentsType::Mut => quote! { get_all_components_mut },
+            GetComponentsType::Regular => quote! { fetch_all },
+            GetComponentsType::Mut => quote! { fetch_all_mut },
         };
         
         if query_ids {
@@ -223,12 +297,12 @@ fn zip(generic_names: &Vec<syn::Ident>, ty: GetComponentsType, query_ids: bool)
             quote! {
                 ::std::iter::zip(
                     #id_iter,
-                    unsafe { #archetype.#func_name ::<#generic_name>(#archetype.get_arch_rows()) }
+                    unsafe { crate::query::#func_name ::<#generic_name>(archetype, #arch_rows #change_tick) }
                 )
             }
         } else {
             quote! {
-                unsafe { #archetype.#func_name ::<#generic_name>(#archetype.get_arch_rows()) }
+                unsafe { crate::query::#func_name ::<#generic_name>(archetype, #arch_rows #change_tick) }
             }
         }
     } else {
@@ -253,7 +327,7 @@ enum GetComponentsType {
 }
 
 // returns the next part of the zip, ends with None
-fn get_next_zip(generic_names: &Vec<syn::Ident>, i: usize, ty: GetComponentsType) -> Option<TokenStream2> {
+fn get_next_zip(generic_names: &[syn::Ident], i: usize, ty: GetComponentsType) -> Option<TokenStream2> {
     if generic_names.len() == i {
         return None;
     }
@@ -263,27 +337,28 @@ fn get_next_zip(generic_names: &Vec<syn::Ident>, i: usize, ty: GetComponentsType
     let next = get_next_zip(generic_names, i + 1, ty);
     
     let func_name = syn::Ident::new(match ty {
-        GetComponentsType::Regular => "get_all_components",
-        GetComponentsType::Mut => "get_all_components_mut",
+        GetComponentsType::Regular => "fetch_all",
+        GetComponentsType::Mut => "fetch_all_mut",
     }, proc_macro2::Span::call_site());
     
-    let archetype = match ty {
-        GetComponentsType::Regular => quote! { archetype },
-        GetComponentsType::Mut => quote! { (*archetype) },
+    let arch_rows = quote! { rows.clone() };
+    let change_tick = match ty {
+        GetComponentsType::Regular => quote! {},
+        GetComponentsType::Mut => quote! { , change_tick },
     };
 
     return Some(match next {
         Some(next) => {
             quote! {
                 ::std::iter::zip(
-                    unsafe { #archetype.#func_name ::<#generic_name>(#archetype.get_arch_rows()/*entities.clone()*/) },
+                    unsafe { crate::query::#func_name ::<#generic_name>(archetype, #arch_rows/*entities.clone()*/ #change_tick) },
                     #next
                 )
             }
         },
         None => {
             quote! {
-                unsafe { #archetype.#func_name ::<#generic_name>(#archetype.get_arch_rows()/*entities*/) }
+                unsafe { crate::query::#func_name ::<#generic_name>(archetype, #arch_rows/*entities*/ #change_tick) }
             }
         }
     });
@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 
 use crate::component::ComponentId;
-use crate::arch::Archetype;
+use crate::entity::{Entity, EntityId};
+use crate::arch::{Archetype, ArchRowId, ComponentInfo, CloneFn};
 
 pub type ArchetypeId = u32;
 
+#[derive(Clone)]
 struct CompMapVal {
     components: Vec<ComponentId>,
     archetype: ArchetypeId,
@@ -13,7 +15,7 @@ struct CompMapVal {
 impl CompMapVal {
     #[inline]
     /// **IMPORTANT** `comps` should be a sorted vector!
-    fn hash_component(comps: &Vec<ComponentId>) -> u32 {
+    fn hash_component(comps: &[ComponentId]) -> u32 {
         // comps.sort();
         let mut total: u32 = 0;
         for comp_id in comps {
@@ -25,7 +27,9 @@ impl CompMapVal {
 
 pub(crate) struct ArchStore {
     pub(crate) archetypes: Vec<Archetype>,
-    comp_map: HashMap<u32, CompMapVal>
+    comp_map: HashMap<u32, CompMapVal>,
+    /// All archetypes containing a component
+    comp_archetypes: HashMap<ComponentId, Vec<ArchetypeId>>,
 }
 
 pub(crate) enum NewEntityResult {
@@ -47,9 +51,21 @@ impl ArchStore {
         Self {
             archetypes: Vec::new(),
             comp_map: HashMap::new(),
+            comp_archetypes: HashMap::new(),
         }
     }
     
+    /// Clones all archetypes, see [`Archetype::try_clone`]
+    pub(crate) fn try_clone(&self, clone_fn: impl Fn(ComponentId) -> Option<CloneFn>) -> Result<ArchStore, ComponentId> {
+        return Ok(Self {
+            archetypes: self.archetypes.iter()
+                .map(|archetype| archetype.try_clone(&clone_fn))
+                .collect::<Result<_, _>>()?,
+            comp_map: self.comp_map.clone(),
+            comp_archetypes: self.comp_archetypes.clone(),
+        });
+    }
+    
     #[inline]
     pub(crate) fn get_archetype(&self, archetype: ArchetypeId) -> &Archetype {
         &self.archetypes[archetype as usize]
@@ -61,26 +77,54 @@ impl ArchStore {
     }
 
     #[inline]
-    pub(crate) fn remove_entity(&mut self, entity: &crate::entity::Entity) {
+    /// Gets all the archetypes that have the component with id `component`
+    pub(crate) fn get_archetypes(&self, component: ComponentId) -> &[ArchetypeId] {
+        match self.comp_archetypes.get(&component) {
+            Some(archetypes) => archetypes,
+            None => &[]
+        }
+    }
+
+    #[inline]
+    /// The archetypes containing all of the given components. `None` entries
+    /// (optional components) are ignored.
+    pub(crate) fn get_archetypes_with(&self, components: &[Option<ComponentId>]) -> Vec<ArchetypeId> {
+        let mut required = components.iter().flatten();
+        match required.next() {
+            Some(first) => {
+                let rest: Vec<ComponentId> = required.copied().collect();
+                self.get_archetypes(*first).iter()
+                    .copied()
+                    .filter(|arch_id| rest.iter().all(|id| self.archetypes[*arch_id as usize].has_component(*id)))
+                    .collect()
+            }
+            None => (0..self.archetypes.len() as ArchetypeId).collect()
+        }
+    }
+    
+    #[inline]
+    /// Removes an entity, dropping its components. Returns the entity that was
+    /// moved into its row, see [`Archetype::remove_entity`]
+    pub(crate) fn remove_entity(&mut self, entity: &Entity) -> Option<EntityId> {
         let arch = &mut self.archetypes[entity.arch_id as usize];
-        arch.remove_entity(entity.arch_row);
+        arch.remove_entity(entity.arch_row)
     }
     
     #[inline]
     /// Get the archetype of a new entity
     /// - `get_component_sizes` is called when a new archetype is created
-    pub(crate) fn get_new_entity_archetype(&mut self, components: &Vec<ComponentId>/*, get_component_sizes: fn() -> Vec<usize>*/) -> NewEntityResult {
+    pub(crate) fn get_new_entity_archetype(&mut self, components: &[ComponentId]/*, get_component_sizes: fn() -> Vec<usize>*/) -> NewEntityResult {
         let comps_hash = CompMapVal::hash_component(components); 
         
         return self.get_archetype_id_for_component_hash(comps_hash, components/*, get_component_sizes*/);
     }
 
     #[inline]
-    fn get_archetype_id_for_component_hash(&mut self, hash: u32, components: &Vec<ComponentId>/*, get_component_sizes: fn() -> Vec<usize>*/) -> NewEntityResult {
+    fn get_archetype_id_for_component_hash(&mut self, hash: u32, components: &[ComponentId]/*, get_component_sizes: fn() -> Vec<usize>*/) -> NewEntityResult {
         match self.comp_map.get(&hash) {
             Some(val) => {
                 // See if correct
-                if &val.components != components {
+                if val.components != components {
                     return self.get_archetype_id_for_component_hash(hash + 1, components/*, get_component_sizes*/);
                 } else {
                     return NewEntityResult::OldArchetype(val.archetype);
@@ -93,14 +137,66 @@ impl ArchStore {
                     Archetype::new(components/*, &get_component_sizes()*/)
                 );
                 self.comp_map.insert(hash, CompMapVal {
-                    components: components.clone(), // doesn't happen often
+                    components: components.to_vec(), // doesn't happen often
                                 // No need to shrink components vec; if using `vec!`, it is already the correct size and `vec.push` doesn't allocate a lot at a time
                     archetype: id,
                 });
+                for comp_id in components {
+                    self.comp_archetypes.entry(*comp_id).or_default().push(id);
+                }
                 return NewEntityResult::NewArchetype(id);
             }
         }
     }
+    
+    /// Moves an entity into the archetype with the given (sorted) `components`,
+    /// creating the archetype if it doesn't exist yet.
+    ///
+    /// Components shared by both archetypes are moved to the new row. Components
+    /// that are not part of the new archetype should be taken out of the old
+    /// row beforehand. `added` describes the component that is not part of the
+    /// entity's current archetype, if any; it should be set by the caller.
+    ///
+    /// Returns the new archetype and row of the entity, and the entity that was
+    /// moved into its old row, of which the row should be updated
+    pub(crate) fn move_entity(
+        &mut self,
+        ent_id: EntityId,
+        entity: &Entity,
+        components: &[ComponentId],
+        added: Option<(ComponentId, ComponentInfo)>
+    ) -> (ArchetypeId, ArchRowId, Option<EntityId>) {
+        let to_id = match self.get_new_entity_archetype(components) {
+            NewEntityResult::NewArchetype(id) => {
+                let from = &self.archetypes[entity.arch_id as usize];
+                let infos: Vec<ComponentInfo> = components.iter()
+                    .map(|comp_id| match added {
+                        Some((added_id, info)) if added_id == *comp_id => info,
+                        _ => from.component_info(*comp_id)
+                    }).collect();
+                self.archetypes[id as usize].init(components, &infos);
+                id
+            }
+            NewEntityResult::OldArchetype(id) => id
+        };
+        
+        // The component sets differ, so the archetypes are never the same
+        debug_assert_ne!(to_id, entity.arch_id);
+        let archetypes = self.archetypes.as_mut_ptr();
+        let (from, to) = unsafe {
+            (&mut *archetypes.add(entity.arch_id as usize), &mut *archetypes.add(to_id as usize))
+        };
+        
+        let to_row = to.new_archrow_id(ent_id);
+        for comp_id in components {
+            if from.has_component(*comp_id) {
+                unsafe { to.move_component_from(from, *comp_id, entity.arch_row, to_row); }
+            }
+        }
+        let moved = from.remove_moved_entity(entity.arch_row);
+        
+        return (to_id, to_row, moved);
+    }
 }
 
 #[cfg(test)]
@@ -112,11 +208,11 @@ mod test {
         let mut arch_store = ArchStore::new();
         let mut v = vec![1, 0];
         v.sort();
-        let id1 = arch_store.get_new_entity_archetype(&vec![0, 1]).unwrap();
-        let id2 = arch_store.get_new_entity_archetype(&vec![0, 2]).unwrap();
+        let id1 = arch_store.get_new_entity_archetype(&[0, 1]).unwrap();
+        let id2 = arch_store.get_new_entity_archetype(&[0, 2]).unwrap();
         let id3 = arch_store.get_new_entity_archetype(&v).unwrap();
-        let id4 = arch_store.get_new_entity_archetype(&vec![]).unwrap();
-        let id5 = arch_store.get_new_entity_archetype(&vec![]).unwrap();
+        let id4 = arch_store.get_new_entity_archetype(&[]).unwrap();
+        let id5 = arch_store.get_new_entity_archetype(&[]).unwrap();
         
         assert_eq!(id1, id3);
         assert_ne!(id2, id1);
@@ -125,4 +221,21 @@ mod test {
         assert_ne!(id4, id3);
         assert_ne!(id4, id2);
     }
+    
+    #[test]
+    fn component_archetypes() {
+        let mut arch_store = ArchStore::new();
+        let id1 = arch_store.get_new_entity_archetype(&[0, 1]).unwrap();
+        let id2 = arch_store.get_new_entity_archetype(&[1, 2]).unwrap();
+        let _ = arch_store.get_new_entity_archetype(&[0, 1]).unwrap();
+        
+        assert_eq!(arch_store.get_archetypes(0), &[id1]);
+        assert_eq!(arch_store.get_archetypes(1), &[id1, id2]);
+        assert_eq!(arch_store.get_archetypes(2), &[id2]);
+        assert_eq!(arch_store.get_archetypes(3), &[]);
+        
+        // archetypes are per store
+        let arch_store = ArchStore::new();
+        assert_eq!(arch_store.get_archetypes(0), &[]);
+    }
 }
@@ -1,5 +1,8 @@
 use std::mem::MaybeUninit;
 use std::collections::HashMap;
+use std::alloc::{self, Layout};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 use crate::component::{Component, ComponentId};
 use crate::entity::EntityId;
@@ -14,33 +17,216 @@ pub(crate) type ArchRowId = u32;
 // Component storage
 //=====================
 
+/// A type-erased array of components, allocated with the layout of the component
 struct ComponentColumn {
-    components: Vec<MaybeUninit<u8>>,
+    components: NonNull<MaybeUninit<u8>>,
+    /// The amount of components that fit in the allocation
+    capacity: usize,
+    /// The layout of a single component, its size is never 0
+    layout: Layout,
 }
 
 impl ComponentColumn {
-    fn new() -> Self {
+    fn new(layout: Layout) -> Self {
+        debug_assert_ne!(layout.size(), 0);
         Self {
-            components: Vec::new(),
+            // dangling, but correctly aligned
+            components: unsafe { NonNull::new_unchecked(layout.align() as *mut MaybeUninit<u8>) },
+            capacity: 0,
+            layout,
+        }
+    }
+    
+    #[inline]
+    fn as_ptr(&self) -> *const MaybeUninit<u8> {
+        self.components.as_ptr()
+    }
+    
+    #[inline]
+    fn as_mut_ptr(&mut self) -> *mut MaybeUninit<u8> {
+        self.components.as_ptr()
+    }
+    
+    /// The layout of an allocation holding `capacity` components
+    #[inline]
+    fn array_layout(&self, capacity: usize) -> Layout {
+        // a type's size is always a multiple of its alignment
+        let size = self.layout.size().checked_mul(capacity).expect("component column capacity overflow");
+        Layout::from_size_align(size, self.layout.align()).expect("component column capacity overflow")
+    }
+    
+    /// Makes sure there is space for a component at index `row`
+    #[inline]
+    fn reserve(&mut self, row: usize) {
+        if row < self.capacity {
+            return;
+        }
+        
+        let new_capacity = (row + 1).max(self.capacity * 2).max(4);
+        let new_layout = self.array_layout(new_capacity);
+        let ptr = unsafe {
+            if self.capacity == 0 {
+                alloc::alloc(new_layout)
+            } else {
+                alloc::realloc(self.components.as_ptr().cast(), self.array_layout(self.capacity), new_layout.size())
+            }
+        };
+        self.components = match NonNull::new(ptr.cast()) {
+            Some(ptr) => ptr,
+            None => alloc::handle_alloc_error(new_layout)
+        };
+        self.capacity = new_capacity;
+    }
+}
+
+impl Drop for ComponentColumn {
+    fn drop(&mut self) {
+        if self.capacity != 0 {
+            unsafe { alloc::dealloc(self.components.as_ptr().cast(), self.array_layout(self.capacity)); }
+        }
+    }
+}
+
+/// Type-erased information about a component type, used to create columns
+/// for archetypes that are not created from a statically known set of types
+#[derive(Clone, Copy)]
+pub(crate) struct ComponentInfo {
+    /// The size of the component in bytes
+    pub(crate) size: usize,
+    /// The alignment of the component in bytes
+    pub(crate) align: usize,
+    /// Drops the component the pointer points to, `None` if the component
+    /// doesn't need to be dropped
+    pub(crate) drop: Option<unsafe fn(*mut u8)>,
+}
+
+impl ComponentInfo {
+    #[inline]
+    pub(crate) fn of<T: Component + 'static>() -> Self {
+        Self {
+            size: std::mem::size_of::<T>(),
+            align: std::mem::align_of::<T>(),
+            drop: if std::mem::needs_drop::<T>() {
+                Some(drop_component::<T>)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// # Safety
+/// `ptr` should point to an initialised `T`. Zero-sized components are not
+/// stored, so `ptr` is ignored for those.
+unsafe fn drop_component<T>(ptr: *mut u8) {
+    if std::mem::size_of::<T>() == 0 {
+        std::ptr::drop_in_place(std::ptr::NonNull::<T>::dangling().as_ptr());
+    } else {
+        std::ptr::drop_in_place(ptr.cast::<T>());
+    }
+}
+
+/// Clones the component `src` points to into `dst`, which points to uninitialised
+/// memory. Zero-sized components are not stored, so the pointers are ignored for those.
+pub(crate) type CloneFn = unsafe fn(*const u8, *mut u8);
+
+/// # Safety
+/// `src` should point to an initialised `T`, `dst` to memory for a `T`
+pub(crate) unsafe fn clone_component<T: Clone>(src: *const u8, dst: *mut u8) {
+    if std::mem::size_of::<T>() == 0 {
+        let ptr = std::ptr::NonNull::<T>::dangling().as_ptr();
+        std::ptr::write(ptr, (*ptr).clone());
+    } else {
+        std::ptr::write(dst.cast::<T>(), (*src.cast::<T>()).clone());
+    }
+}
+
+/// The ticks at which a component was added and last changed, see [`World::change_tick`](crate::World::change_tick)
+#[derive(Default)]
+pub(crate) struct ComponentTicks {
+    pub(crate) added: u32,
+    /// Atomic, because mutable queries running on different threads set the
+    /// ticks of different components through a shared archetype
+    changed: AtomicU32,
+}
+
+impl ComponentTicks {
+    #[inline]
+    fn new(tick: u32) -> Self {
+        Self {
+            added: tick,
+            changed: AtomicU32::new(tick),
+        }
+    }
+    
+    #[inline]
+    pub(crate) fn changed(&self) -> u32 {
+        self.changed.load(Ordering::Relaxed)
+    }
+    
+    #[inline]
+    pub(crate) fn set_changed(&self, tick: u32) {
+        self.changed.store(tick, Ordering::Relaxed);
+    }
+}
+
+impl Clone for ComponentTicks {
+    fn clone(&self) -> Self {
+        Self {
+            added: self.added,
+            changed: AtomicU32::new(self.changed()),
         }
     }
 }
 
 struct ComponentColumnWrapper {
     val: Option<ComponentColumn>,
-    /// The size of the component in bytes
-    size: usize
+    info: ComponentInfo,
+    /// The ticks of the component in each row, also stored for zero-sized components
+    ticks: Vec<ComponentTicks>,
 }
 
 impl ComponentColumnWrapper {
-    fn new(size: usize) -> Self {
+    fn new(info: ComponentInfo) -> Self {
         Self {
-            val: if size == 0 {
+            val: if info.size == 0 {
                 None
             } else {
-                Some(ComponentColumn::new())
+                let layout = Layout::from_size_align(info.size, info.align)
+                    .expect("invalid component layout");
+                Some(ComponentColumn::new(layout))
             },
-            size,
+            info,
+            ticks: Vec::new(),
+        }
+    }
+    
+    /// Sets the ticks of the component in `row`, which was just added
+    #[inline]
+    fn init_ticks(&mut self, row: ArchRowId, ticks: ComponentTicks) {
+        let row = row as usize;
+        if self.ticks.len() <= row {
+            self.ticks.resize_with(row + 1, ComponentTicks::default);
+        }
+        self.ticks[row] = ticks;
+    }
+    
+    /// Pointer to the component in `row`, null for zero-sized components
+    #[inline]
+    fn row_ptr(&self, row: usize) -> *mut u8 {
+        match &self.val {
+            Some(col) => unsafe { col.components.as_ptr().add(row * self.info.size).cast() },
+            None => std::ptr::null_mut()
+        }
+    }
+    
+    /// Pointer to the first component in the column. Zero-sized components
+    /// are not stored, for those a dangling, aligned pointer is returned.
+    #[inline]
+    fn components_ptr<T>(&self) -> *mut MaybeUninit<T> {
+        match &self.val {
+            Some(col) => col.components.as_ptr().cast(),
+            None => NonNull::<MaybeUninit<T>>::dangling().as_ptr()
         }
     }
 }
@@ -49,58 +235,89 @@ impl ComponentColumnWrapper {
 // Archetype
 //=====================
 
-pub(crate) struct Archetype {
+/// Stores the components of all entities with the same set of components.
+///
+/// The rows are densely packed: removing an entity moves the last row into
+/// the removed row, so that all rows below `entities.len()` contain an entity.
+///
+/// Public because the hidden query traits take archetypes, the type can't be
+/// named outside of the crate.
+pub struct Archetype {
     components: HashMap<ComponentId, ComponentColumnWrapper>,
-    available_ent_ids: Vec<ArchRowId>,
+    /// The entity in each row
     entities: Vec<EntityId>,
 }
 
 impl Archetype {
     /// When calling the function, the `Archetype::init` function should also be called
-    pub(crate) fn new(components: &Vec<ComponentId>) -> Self {
+    pub(crate) fn new(components: &[ComponentId]) -> Self {
         Self {
             components: HashMap::with_capacity(components.len()), //comps,
-            available_ent_ids: Vec::new(),
             entities: Vec::new()
         }
     }
     
-    pub(crate) fn init(&mut self, components: &Vec<ComponentId>, sizes: &Vec<usize>) {
-        for i in 0..sizes.len() {
-            self.components.insert(components[i], ComponentColumnWrapper::new(sizes[i]));
+    pub(crate) fn init(&mut self, components: &[ComponentId], infos: &[ComponentInfo]) {
+        for i in 0..infos.len() {
+            self.components.insert(components[i], ComponentColumnWrapper::new(infos[i]));
         }
     }
     
     #[inline]
-    /// Get an empty entity id
+    /// Adds a row for `entity` at the end of the archetype. Its components
+    /// should be initialised afterwards.
     pub(crate) fn new_archrow_id(&mut self, entity: EntityId) -> ArchRowId {
-        if let Some(id) = self.available_ent_ids.pop() {
-            self.entities[id as usize] = entity;
-            return id;
-        } else {
-            let id = self.entities.len();
-            self.entities.push(entity);
-            return id as ArchRowId;
+        let id = self.entities.len();
+        self.entities.push(entity);
+        // the ticks are set when the components are initialised
+        for component_col_wrap in self.components.values_mut() {
+            component_col_wrap.ticks.push(ComponentTicks::default());
         }
+        return id as ArchRowId;
     }
 
     #[inline]
-    pub(crate) fn set_component<T: Component + 'static>(&mut self, entity_id: ArchRowId, component: T) {
+    /// Overwrites the component of type `T` for entity with arch row `entity_id`,
+    /// dropping the old value. The component is marked as changed at `tick`.
+    pub(crate) fn set_component<T: Component + 'static>(&mut self, entity_id: ArchRowId, component: T, tick: u32) {
+        let component_col_wrap = self.components.get_mut(&T::id())
+            .unwrap_or_else(|| panic!("Component {} does not exist for entity with id {}", std::any::type_name::<T>(), entity_id));
+        
+        component_col_wrap.ticks[entity_id as usize].set_changed(tick);
+        if component_col_wrap.info.size == 0 {
+            // zero-sized components are never stored, so drop the old one and
+            // forget the new one to keep the amount of drops correct
+            unsafe { drop_component::<T>(std::ptr::null_mut()); }
+            std::mem::forget(component);
+            return;
+        }
+        let component_col = unsafe { component_col_wrap.val.as_mut().unwrap_unchecked() };
+        let comps_ptr: *mut MaybeUninit<u8> = component_col.as_mut_ptr();
+        let comps_ptr: *mut MaybeUninit<T> = comps_ptr.cast();
+        
+        unsafe { *(*comps_ptr.offset(entity_id as isize)).assume_init_mut() = component; }
+    }
+    
+    #[inline]
+    /// Writes the component of type `T` to a row that doesn't contain an
+    /// initialised component of this type yet (e.g. a newly spawned entity).
+    /// The component is marked as added at `tick`.
+    pub(crate) fn init_component<T: Component + 'static>(&mut self, entity_id: ArchRowId, component: T, tick: u32) {
         let component_col_wrap = self.components.get_mut(&T::id())
-            .expect(&format!("Component {} does not exist for entity with id {}", std::any::type_name::<T>(), entity_id));
+            .unwrap_or_else(|| panic!("Component {} does not exist for entity with id {}", std::any::type_name::<T>(), entity_id));
 
-        // TODO: not necessary anymore (maybe keep in debug)
-        if component_col_wrap.size == 0 {
+        component_col_wrap.init_ticks(entity_id, ComponentTicks::new(tick));
+        debug_assert_eq!(component_col_wrap.info.align, std::mem::align_of::<T>());
+        if component_col_wrap.info.size == 0 {
+            std::mem::forget(component);
             return;
         }
         // size is not 0, so is component column type
         let component_col = unsafe { component_col_wrap.val.as_mut().unwrap_unchecked() };
         
         // should make the function safe + checks above (size != 0 && component exists for this archetype)
-        if component_col.components.len() <= entity_id as usize * component_col_wrap.size {
-            component_col.components.resize_with((entity_id as usize) * component_col_wrap.size + component_col_wrap.size, MaybeUninit::uninit);
-        }
-        let comps_ptr: *mut MaybeUninit<u8> = component_col.components.as_mut_ptr();
+        component_col.reserve(entity_id as usize);
+        let comps_ptr: *mut MaybeUninit<u8> = component_col.as_mut_ptr();
         let comps_ptr: *mut MaybeUninit<T> = comps_ptr.cast();
         
         unsafe { *(comps_ptr.offset(entity_id as isize)) = MaybeUninit::new(component); }
@@ -110,91 +327,73 @@ impl Archetype {
     /// Get component of type `T` for entity with arch row `entity_id`
     ///
     /// # Safety
-    /// No checks are performed whether the component is a unit struct
+    /// The row should contain an initialised component of type `T`
     pub(crate) unsafe fn get_component<T: Component + 'static>(&self, entity_id: ArchRowId) -> &T {
         let component_col_wrap = self.components.get(&T::id())
-            .expect(&format!("Component {} does not exist for entity with id {}", std::any::type_name::<T>(), entity_id)); // TODO: entity id is not right
+            .unwrap_or_else(|| panic!("Component {} does not exist for entity with id {}", std::any::type_name::<T>(), entity_id)); // TODO: entity id is not right
 
-        // &component_col.components[entity_id as usize]
-        let component_col = component_col_wrap.val.as_ref().unwrap_unchecked();
-        let comps_ptr: *const MaybeUninit<u8> = component_col.components.as_ptr();
-        let comps_ptr: *const MaybeUninit<T> = comps_ptr.cast();
-        (comps_ptr.offset(entity_id as isize)).as_ref().unwrap_unchecked().assume_init_ref()
+        let comps_ptr: *const MaybeUninit<T> = component_col_wrap.components_ptr();
+        (*comps_ptr.offset(entity_id as isize)).assume_init_ref()
     }
     
     #[inline]
+    /// Get component of type `T` for entity with arch row `entity_id` mutably,
+    /// marking it as changed at `tick`
+    ///
     /// # Safety
-    /// No checks are performed whether the component is a flag
-    pub(crate) unsafe fn get_component_mut<T: Component + 'static>(&mut self, entity_id: ArchRowId) -> &mut T {
+    /// The row should contain an initialised component of type `T`
+    pub(crate) unsafe fn get_component_mut<T: Component + 'static>(&mut self, entity_id: ArchRowId, tick: u32) -> &mut T {
         let component_col_wrap = self.components.get_mut(&T::id())
-            .expect(&format!("Component {} does not exist for entity with id {}", std::any::type_name::<T>(), entity_id)); // TODO: entity id is not right
+            .unwrap_or_else(|| panic!("Component {} does not exist for entity with id {}", std::any::type_name::<T>(), entity_id)); // TODO: entity id is not right
         
-        let component_col = component_col_wrap.val.as_mut().unwrap_unchecked();
-        let comps_ptr: *mut MaybeUninit<u8> = component_col.components.as_mut_ptr();
-        let comps_ptr: *mut MaybeUninit<T> = comps_ptr.cast();
-        (comps_ptr.offset(entity_id as isize)).as_mut().unwrap_unchecked().assume_init_mut()
+        component_col_wrap.ticks[entity_id as usize].set_changed(tick);
+        let comps_ptr: *mut MaybeUninit<T> = component_col_wrap.components_ptr();
+        (*comps_ptr.offset(entity_id as isize)).assume_init_mut()
     }
     
+    /// Pointer to the first component with id `id` and the size of the component.
+    /// `None` if the archetype doesn't contain the component.
     #[inline]
-    pub(crate) unsafe fn get_all_components
-        <'a, T: Component + 'static>
-        (
-            &'a self, 
-            ent_ids: impl std::iter::Iterator<Item = ArchRowId>
-        )
-        -> impl std::iter::Iterator<Item = &'a T>
-    {
-        let component_col_wrap = self.components.get(&T::id())
-            .expect(&format!("Component {} does not exist for the given entities", std::any::type_name::<T>()));
-            // .expect(&format!("Component {} does not exist for the entities with ids {:?}", std::any::type_name::<T>(), ent_ids));
-        
-        let component_col = component_col_wrap.val.as_ref().unwrap_unchecked();
-        let comps_ptr: *const MaybeUninit<u8> = component_col.components.as_ptr();
-        let comps_ptr: *const MaybeUninit<T> = comps_ptr.cast();
-        
-        ent_ids.into_iter()
-            .map(move |ent_id| { // move comps_ptr
-                let comp = comps_ptr.offset(ent_id as isize).as_ref().unwrap_unchecked();
-                comp.assume_init_ref()
-            })
+    pub(crate) fn column_ptr(&self, id: ComponentId) -> Option<(NonNull<u8>, usize)> {
+        let component_col_wrap = self.components.get(&id)?;
+        let ptr = match &component_col_wrap.val {
+            Some(col) => col.components.cast(),
+            // zero-sized components are not stored; dangling, but correctly aligned
+            None => unsafe { NonNull::new_unchecked(component_col_wrap.info.align as *mut u8) }
+        };
+        return Some((ptr, component_col_wrap.info.size));
     }
-
+    
+    /// The ticks of the components with id `id`, indexed by row. `None` if the
+    /// archetype doesn't contain the component.
+    /// Moves the ticks at which the components were added and changed forward
+    /// by `offset` ticks
+    pub(crate) fn offset_ticks(&mut self, offset: u32) {
+        for component_col_wrap in self.components.values_mut() {
+            for ticks in &mut component_col_wrap.ticks {
+                ticks.added = ticks.added.wrapping_add(offset);
+                let changed = ticks.changed.get_mut();
+                *changed = changed.wrapping_add(offset);
+            }
+        }
+    }
+    
     #[inline]
-    pub(crate) unsafe fn get_all_components_mut<
-        'a, T: Component + 'static
-    >(
-        &'a mut self,
-        ent_ids: impl std::iter::Iterator<Item = ArchRowId>,
-    ) -> impl std::iter::Iterator<Item = &'a mut T> 
-    {
-        let component_col_wrap = self.components.get_mut(&T::id())
-            .expect(&format!("Component {} does not exist for the given entities", std::any::type_name::<T>()));
-        
-        let component_col = component_col_wrap.val.as_mut().unwrap_unchecked();
-        let comps_ptr: *mut MaybeUninit<u8> = component_col.components.as_mut_ptr();
-        let comps_ptr: *mut MaybeUninit<T> = comps_ptr.cast();
-        
-        ent_ids
-            .map(move |ent_id| {
-                let comp = comps_ptr.offset(ent_id as isize).as_mut().unwrap_unchecked();
-                comp.assume_init_mut()
-            })
+    pub(crate) fn component_ticks(&self, id: ComponentId) -> Option<&[ComponentTicks]> {
+        self.components.get(&id).map(|component_col_wrap| component_col_wrap.ticks.as_slice())
     }
     
     #[inline]
     #[allow(unused)] // TODO: use again in query_mut_ptr
     /// Get all components of type `T` for the entities with ids `ent_ids`
-    pub(crate) unsafe fn get_all_components_mut_ptr<T: Component + 'static>(&mut self, ent_ids: &Vec<EntityId>) -> Vec<*mut T> {
+    pub(crate) unsafe fn get_all_components_mut_ptr<T: Component + 'static>(&mut self, ent_ids: &[ArchRowId]) -> Vec<*mut T> {
         let component_col_wrap = self.components.get_mut(&T::id())
-            .expect(&format!("Component {} does not exist for the entities with ids {:?}", std::any::type_name::<T>(), ent_ids));
+            .unwrap_or_else(|| panic!("Component {} does not exist for the entities with ids {:?}", std::any::type_name::<T>(), ent_ids));
         
-        let component_col = component_col_wrap.val.as_mut().unwrap_unchecked();
-        let comps_ptr: *mut MaybeUninit<u8> = component_col.components.as_mut_ptr();
-        let comps_ptr: *mut MaybeUninit<T> = comps_ptr.cast();
+        let comps_ptr: *mut MaybeUninit<T> = component_col_wrap.components_ptr();
         ent_ids.iter()
             .map(|ent_id| {
-                let comp = comps_ptr.offset(*ent_id as isize).as_mut().unwrap_unchecked();
-                let comp: *mut T = comp.assume_init_mut();
+                let comp: *mut T = (*comps_ptr.offset(*ent_id as isize)).assume_init_mut();
                 comp
                 // let dyn_comp = &mut**unsafe { component_col.components[*ent_id as usize].assume_init_mut() };
                 // let comp = dyn_comp.as_any_mut().downcast_mut::<T>();
@@ -204,25 +403,27 @@ impl Archetype {
     }
     
     #[inline]
-    pub(crate) fn get_arch_rows<'a, 'b>(&'a self) 
-        -> impl std::iter::Iterator<Item = ArchRowId> + 'a
-        where 'b: 'a
-    {
-        self.entities.iter()
-            .enumerate()
-            .filter(|(row, _)| !self.available_ent_ids.contains(&(*row as u32)))
-            .map(|(row, _)| row as u32)
+    pub(crate) fn get_arch_rows(&self) -> std::ops::Range<ArchRowId> {
+        0..self.entities.len() as ArchRowId
     }
     
+    /// The ids of the entities in the given rows
     #[inline]
-    pub(crate) fn get_entity_ids<'a, 'b>(&'a self)
+    pub(crate) fn get_entity_ids_of<'a>(&'a self, rows: impl std::iter::Iterator<Item = ArchRowId> + 'a)
         -> impl std::iter::Iterator<Item = EntityId> + 'a
-        where 'b: 'a
     {
-        self.entities.iter()
-            .enumerate()
-            .filter(|(row, _)| !self.available_ent_ids.contains(&(*row as u32)))
-            .map(|(_, id)| *id)
+        rows.map(|row| self.entities[row as usize])
+    }
+    
+    /// The entity in each row
+    #[inline]
+    pub(crate) fn entities(&self) -> &[EntityId] {
+        &self.entities
+    }
+    
+    #[inline]
+    pub(crate) fn get_entity_id(&self, row: ArchRowId) -> EntityId {
+        self.entities[row as usize]
     }
     
     #[inline]
@@ -230,8 +431,180 @@ impl Archetype {
         self.components.contains_key(&id)
     }
     
+    /// The sorted ids of the components in this archetype
+    pub(crate) fn component_ids(&self) -> Vec<ComponentId> {
+        let mut ids: Vec<ComponentId> = self.components.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+    
+    #[inline]
+    pub(crate) fn component_info(&self, id: ComponentId) -> ComponentInfo {
+        self.components[&id].info
+    }
+    
+    /// Moves the component of type `T` out of row `entity_id`.
+    ///
+    /// # Safety
+    /// The row should contain an initialised component of type `T`, which must
+    /// be treated as uninitialised afterwards.
+    pub(crate) unsafe fn take_component<T: Component + 'static>(&mut self, entity_id: ArchRowId) -> T {
+        let component_col_wrap = self.components.get_mut(&T::id())
+            .unwrap_or_else(|| panic!("Component {} does not exist for entity with id {}", std::any::type_name::<T>(), entity_id));
+        
+        if component_col_wrap.info.size == 0 {
+            return std::ptr::read(std::ptr::NonNull::<T>::dangling().as_ptr());
+        }
+        let component_col = component_col_wrap.val.as_mut().unwrap_unchecked();
+        let comps_ptr: *mut MaybeUninit<u8> = component_col.as_mut_ptr();
+        let comps_ptr: *mut MaybeUninit<T> = comps_ptr.cast();
+        (*comps_ptr.offset(entity_id as isize)).assume_init_read()
+    }
+    
+    /// Moves the component with id `comp_id` from row `from_row` in `from` to
+    /// row `to_row` in this archetype by copying its bytes.
+    ///
+    /// # Safety
+    /// `from_row` should contain an initialised component, which must be treated
+    /// as uninitialised afterwards. Both archetypes should contain the component.
+    pub(crate) unsafe fn move_component_from(&mut self, from: &Archetype, comp_id: ComponentId, from_row: ArchRowId, to_row: ArchRowId) {
+        let from_ticks = &from.components[&comp_id].ticks[from_row as usize];
+        self.components.get_mut(&comp_id).unwrap_unchecked().init_ticks(to_row, ComponentTicks {
+            added: from_ticks.added,
+            changed: AtomicU32::new(from_ticks.changed()),
+        });
+        
+        let size = from.components[&comp_id].info.size;
+        if size == 0 {
+            return;
+        }
+        let src_col = from.components[&comp_id].val.as_ref().unwrap_unchecked();
+        let dst_col = self.components.get_mut(&comp_id).unwrap_unchecked().val.as_mut().unwrap_unchecked();
+        
+        dst_col.reserve(to_row as usize);
+        std::ptr::copy_nonoverlapping(
+            src_col.as_ptr().add(from_row as usize * size),
+            dst_col.as_mut_ptr().add(to_row as usize * size),
+            size
+        );
+    }
+    
+    /// Drops all components in the given row
+    ///
+    /// # Safety
+    /// The row should contain initialised components, which must be treated as
+    /// uninitialised afterwards
+    unsafe fn drop_row(&mut self, arch_row: ArchRowId) {
+        for component_col_wrap in self.components.values_mut() {
+            if let Some(drop) = component_col_wrap.info.drop {
+                let ptr = match &mut component_col_wrap.val {
+                    Some(component_col) => component_col.as_mut_ptr()
+                        .add(arch_row as usize * component_col_wrap.info.size)
+                        .cast::<u8>(),
+                    None => std::ptr::null_mut(),
+                };
+                drop(ptr);
+            }
+        }
+    }
+    
+    /// Drops the component with id `id` in row `arch_row`
+    ///
+    /// # Safety
+    /// The row should contain an initialised component with this id, which must
+    /// be treated as uninitialised afterwards
+    pub(crate) unsafe fn drop_component(&mut self, id: ComponentId, arch_row: ArchRowId) {
+        let component_col_wrap = self.components.get_mut(&id).unwrap_unchecked();
+        if let Some(drop) = component_col_wrap.info.drop {
+            let ptr = match &mut component_col_wrap.val {
+                Some(component_col) => component_col.as_mut_ptr()
+                    .add(arch_row as usize * component_col_wrap.info.size)
+                    .cast::<u8>(),
+                None => std::ptr::null_mut(),
+            };
+            drop(ptr);
+        }
+    }
+    
+    /// Clones the archetype, using `clone_fn` to get the function cloning each
+    /// component. Returns the id of the first component that can't be cloned
+    /// if `clone_fn` returns `None` for it.
+    pub(crate) fn try_clone(&self, clone_fn: impl Fn(ComponentId) -> Option<CloneFn>) -> Result<Archetype, ComponentId> {
+        let clone_fns: Vec<(ComponentId, CloneFn)> = self.component_ids().into_iter()
+            .map(|id| clone_fn(id).map(|clone| (id, clone)).ok_or(id))
+            .collect::<Result<_, _>>()?;
+        
+        let mut archetype = Archetype {
+            components: HashMap::with_capacity(self.components.len()),
+            // entities are added once their components are cloned, so only
+            // those are dropped if cloning a component panics
+            entities: Vec::with_capacity(self.entities.len()),
+        };
+        for (id, component_col_wrap) in &self.components {
+            let mut column = ComponentColumnWrapper::new(component_col_wrap.info);
+            if let (Some(col), Some(last_row)) = (&mut column.val, self.entities.len().checked_sub(1)) {
+                col.reserve(last_row);
+            }
+            column.ticks = component_col_wrap.ticks.clone();
+            archetype.components.insert(*id, column);
+        }
+        
+        for (row, entity) in self.entities.iter().enumerate() {
+            for (id, clone) in &clone_fns {
+                let src = self.components[id].row_ptr(row);
+                let dst = archetype.components[id].row_ptr(row);
+                unsafe { clone(src, dst); }
+            }
+            archetype.entities.push(*entity);
+        }
+        return Ok(archetype);
+    }
+    
+    /// Moves the last row into `arch_row` by copying its bytes, and removes
+    /// the last row. Returns the entity that was moved into `arch_row`, `None`
+    /// if `arch_row` was the last row.
+    ///
+    /// # Safety
+    /// The components in `arch_row` should be uninitialised, i.e. dropped or moved out
+    unsafe fn swap_remove_row(&mut self, arch_row: ArchRowId) -> Option<EntityId> {
+        let row = arch_row as usize;
+        let last = self.entities.len() - 1;
+        self.entities.swap_remove(row);
+        for component_col_wrap in self.components.values_mut() {
+            component_col_wrap.ticks.swap_remove(row);
+            if row != last {
+                if let Some(component_col) = &mut component_col_wrap.val {
+                    let size = component_col_wrap.info.size;
+                    let ptr = component_col.as_mut_ptr();
+                    std::ptr::copy_nonoverlapping(ptr.add(last * size), ptr.add(row * size), size);
+                }
+            }
+        }
+        return if row != last { Some(self.entities[row]) } else { None };
+    }
+    
+    #[inline]
+    /// Removes an entity from the archetype, dropping its components. Returns
+    /// the entity that was moved into its row, of which the row should be updated.
+    pub(crate) fn remove_entity(&mut self, arch_row: ArchRowId) -> Option<EntityId> {
+        unsafe {
+            self.drop_row(arch_row);
+            self.swap_remove_row(arch_row)
+        }
+    }
+    
     #[inline]
-    pub(crate) fn remove_entity(&mut self, arch_row: ArchRowId) {
-        self.available_ent_ids.push(arch_row);
+    /// Removes an entity of which the components have been moved out of the
+    /// archetype. Returns the entity that was moved into its row.
+    pub(crate) fn remove_moved_entity(&mut self, arch_row: ArchRowId) -> Option<EntityId> {
+        unsafe { self.swap_remove_row(arch_row) }
+    }
+}
+
+impl Drop for Archetype {
+    fn drop(&mut self) {
+        for row in 0..self.entities.len() as ArchRowId {
+            unsafe { self.drop_row(row); }
+        }
     }
 }
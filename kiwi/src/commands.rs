@@ -0,0 +1,130 @@
+use std::sync::Arc;
+
+use crate::component::{Component, Flag, FlagId};
+use crate::entity::{EntityId, IdReserver};
+use crate::world::World;
+
+enum Command {
+    /// Spawns an entity with an id reserved in the given epoch
+    Spawn(EntityId, u32),
+    Kill(EntityId),
+    SetFlag(EntityId, FlagId),
+    UnsetFlag(EntityId, FlagId),
+    /// Commands on an entity that need to know the component type, like inserting a component
+    Entity(EntityId, Box<dyn FnOnce(&mut World)>),
+    Custom(Box<dyn FnOnce(&mut World)>),
+}
+
+impl Command {
+    /// The entity the command changes
+    #[inline]
+    fn entity(&self) -> Option<EntityId> {
+        return match self {
+            Command::Spawn(id, _) | Command::Kill(id) | Command::SetFlag(id, _) | Command::UnsetFlag(id, _) | Command::Entity(id, _) => Some(*id),
+            Command::Custom(_) => None,
+        };
+    }
+}
+
+/// Records changes to a world, which are applied later using
+/// [`World::apply_commands`].
+///
+/// This allows spawning and killing entities or changing their components
+/// while the world is borrowed, e.g. while iterating over a query.
+pub struct Commands {
+    /// The counter of the world's entity store, used to reserve ids
+    next_id: Arc<IdReserver>,
+    commands: Vec<Command>,
+}
+
+impl Commands {
+    /// Creates an empty command buffer for `world`
+    pub fn new(world: &World) -> Self {
+        Self {
+            next_id: world.id_reserver().clone(),
+            commands: Vec::new(),
+        }
+    }
+    
+    /// Spawns an entity without components. The returned id can be used in
+    /// other commands right away, but the entity is only alive after the
+    /// commands are applied.
+    ///
+    /// If the commands are dropped without being applied, the id is never used.
+    /// If the world is restored from a snapshot before the commands are applied,
+    /// the id may be handed out again, so the entity isn't spawned and the
+    /// other commands for it are skipped.
+    pub fn spawn(&mut self) -> EntityId {
+        let (index, epoch) = self.next_id.reserve();
+        let id = EntityId::new(index, 0);
+        self.commands.push(Command::Spawn(id, epoch));
+        return id;
+    }
+    
+    /// Kills an entity
+    pub fn kill(&mut self, entity: EntityId) {
+        self.commands.push(Command::Kill(entity));
+    }
+    
+    /// Adds a component to an entity, overwriting the existing one
+    pub fn insert<T: Component + 'static>(&mut self, entity: EntityId, component: T) {
+        self.commands.push(Command::Entity(entity, Box::new(move |world: &mut World| {
+            world.insert_component(entity, component);
+        })));
+    }
+    
+    /// Removes a component from an entity, dropping it
+    pub fn remove<T: Component + 'static>(&mut self, entity: EntityId) {
+        self.commands.push(Command::Entity(entity, Box::new(move |world: &mut World| {
+            world.remove_component::<T>(entity);
+        })));
+    }
+    
+    /// Sets a flag for an entity
+    pub fn set_flag<F: Flag>(&mut self, entity: EntityId, flag: F) {
+        self.commands.push(Command::SetFlag(entity, flag.into()));
+    }
+    
+    /// Removes a flag from an entity
+    pub fn unset_flag<F: Flag>(&mut self, entity: EntityId, flag: F) {
+        self.commands.push(Command::UnsetFlag(entity, flag.into()));
+    }
+    
+    /// Records a custom command
+    pub fn add(&mut self, command: impl FnOnce(&mut World) + 'static) {
+        self.commands.push(Command::Custom(Box::new(command)));
+    }
+    
+    /// The amount of recorded commands
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+    
+    /// Applies all recorded commands in order and clears the buffer
+    pub(crate) fn apply(&mut self, world: &mut World) {
+        assert!(
+            Arc::ptr_eq(&self.next_id, world.id_reserver()),
+            "Commands can only be applied to the world they were created for"
+        );
+        let epoch = world.id_reserver().epoch();
+        // ids reserved before the world was restored, which may belong to other entities now
+        let mut invalid: Vec<EntityId> = Vec::new();
+        for command in self.commands.drain(..) {
+            if command.entity().is_some_and(|id| invalid.contains(&id)) {
+                continue;
+            }
+            match command {
+                Command::Spawn(id, reserved_in) if reserved_in != epoch => invalid.push(id),
+                Command::Spawn(id, _) => world.spawn_reserved(id),
+                Command::Kill(id) => world.kill(id),
+                Command::SetFlag(id, flag) => world.set_flag_id(id, flag),
+                Command::UnsetFlag(id, flag) => world.unset_flag_id(id, flag),
+                Command::Entity(_, command) | Command::Custom(command) => command(world),
+            }
+        }
+    }
+}
@@ -1,34 +1,120 @@
-use std::sync::RwLockReadGuard;
-
-use crate::arch_store::ArchetypeId;
+use std::any::TypeId;
+use std::collections::BTreeMap;
+use std::sync::RwLock;
 
 pub type ComponentId = u32;
 
 pub trait Component {
-    /// Gets all the archetypes that have this component. Only used internally
-    fn get_archetypes() -> RwLockReadGuard<'static, Vec<ArchetypeId>>; // RwLockReadGuard<'static, Arc<Vec<ArchetypeId>>> where Self: Sized;
-    /// Add an archetype to this component. Only used internally
-    fn add_archetype(arch: ArchetypeId) where Self: Sized;
     /// The id of this component. Only used internally
     fn id() -> ComponentId where Self: Sized;
 }
 
+/// Assigns ids to components at runtime, the first time they are used. This
+/// keeps ids unique when components are defined in multiple crates.
+struct ComponentRegistry {
+    ids: BTreeMap<TypeId, ComponentId>,
+    /// The type names of the components, indexed by id
+    names: Vec<&'static str>,
+}
+
+static COMPONENT_REGISTRY: RwLock<ComponentRegistry> = RwLock::new(ComponentRegistry {
+    ids: BTreeMap::new(),
+    names: Vec::new(),
+});
+
+/// Returns the id of component `T`, assigning a new one if `T` doesn't have an id yet.
+/// Only used internally
+#[doc(hidden)]
+pub fn component_id<T: 'static>() -> ComponentId {
+    component_id_with::<T>(|_| {})
+}
+
+/// Returns the id of component `T` like [`component_id`], calling `register`
+/// with the new id when the id is assigned, so that it's called once per type.
+/// Only used internally, to register serializable and cloneable components
+#[doc(hidden)]
+pub fn component_id_with<T: 'static>(register: fn(ComponentId)) -> ComponentId {
+    let type_id = TypeId::of::<T>();
+    if let Some(id) = COMPONENT_REGISTRY.read().unwrap().ids.get(&type_id) {
+        return *id;
+    }
+    
+    let id = {
+        let mut registry = COMPONENT_REGISTRY.write().unwrap();
+        // another thread might have registered the component in the mean time
+        if let Some(id) = registry.ids.get(&type_id) {
+            return *id;
+        }
+        let id = registry.names.len() as ComponentId;
+        registry.names.push(std::any::type_name::<T>());
+        registry.ids.insert(type_id, id);
+        id
+    };
+    // the registry is unlocked, registering may look up component names
+    register(id);
+    return id;
+}
+
+/// Returns the type name of the component with the given id
+pub(crate) fn component_name(id: ComponentId) -> Option<&'static str> {
+    COMPONENT_REGISTRY.read().unwrap().names.get(id as usize).copied()
+}
+
+pub type FlagId = u32;
+
+pub trait Flag: std::convert::Into<FlagId> {}
+
 #[cfg(test)]
 mod tests {
     use crate::*;
     use crate as kiwi_ecs;
-
+    use super::component_name;
+    
+    #[derive(Component)]
+    #[allow(dead_code)]
+    struct A(u8);
+    
+    #[derive(Component)]
+    #[allow(dead_code)]
+    struct B(u8);
+    
     #[derive(Component)]
-    struct SomeComponent(u8);
+    #[allow(dead_code)]
+    struct Generic<T>(T);
     
     #[test]
-    fn static_archetypes() {
-        assert_eq!(*SomeComponent::get_archetypes(), Vec::<u32>::new());
-        SomeComponent::add_archetype(5);
-        assert_eq!(*SomeComponent::get_archetypes(), vec![5]);
+    fn component_ids() {
+        assert_ne!(A::id(), B::id());
+        assert_eq!(A::id(), A::id());
+        assert_eq!(A::id(), component_id::<A>());
+        assert_ne!(Generic::<u8>::id(), Generic::<u16>::id());
+        assert_eq!(Generic::<u8>::id(), Generic::<u8>::id());
+        
+        assert_eq!(component_name(A::id()), Some(std::any::type_name::<A>()));
+        assert_eq!(component_name(Generic::<u16>::id()), Some(std::any::type_name::<Generic<u16>>()));
+    }
+    
+    #[test]
+    fn register_once() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        
+        static REGISTERED: AtomicU32 = AtomicU32::new(0);
+        
+        struct Registered<T>(#[allow(dead_code)] T);
+        
+        impl<T: 'static> Component for Registered<T> {
+            fn id() -> ComponentId {
+                super::component_id_with::<Self>(|_| {
+                    REGISTERED.fetch_add(1, Ordering::Relaxed);
+                })
+            }
+        }
+        
+        // once per type, not on every call
+        for _ in 0..3 {
+            Registered::<u8>::id();
+        }
+        Registered::<u16>::id();
+        assert_eq!(REGISTERED.load(Ordering::Relaxed), 2);
     }
 }
-
-pub type FlagId = u32;
-
-pub trait Flag: std::convert::Into<FlagId> {}
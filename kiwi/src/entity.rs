@@ -1,118 +1,368 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+
 use crate::arch::{ArchetypeId, ArchRowId};
 use crate::component::FlagId;
+use crate::error::KiwiError;
+
+/// A handle to an entity.
+///
+/// Consists of an index and a generation. The index of a killed entity is
+/// reused for new entities, but with a new generation. This means that handles
+/// to killed entities can be detected, even if their index is in use again.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    #[inline]
+    pub(crate) fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
+    }
+    
+    /// The index of the entity. Indices are reused after an entity is killed
+    #[inline]
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+    
+    /// The generation of the entity's index, this increases every time the
+    /// index is reused
+    #[inline]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
 
-pub type EntityId = u32;
+impl std::fmt::Display for EntityId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
 
+#[derive(Clone)]
 pub(crate) struct Entity {
     pub(crate) arch_id: ArchetypeId,
     pub(crate) arch_row: ArchRowId,
 }
 
+/// Allocates entity ids and maps them to their archetype row.
+///
+/// Every index below `generations.len()` is in one of three states:
+/// - alive: the entity with the current generation exists
+/// - free: the entity was killed and the index is in `available_ids`, `new_id`
+///   hands it out again with the next generation
+/// - reserved: the index was taken from `next_id` by `Commands`, but the entity
+///   wasn't spawned yet. Reserved indices become alive in `spawn_reserved`.
 pub(crate) struct EntityStore {
-    next_id: EntityId,
-    dead: Vec<u8>,
+    /// Hands out unused indices, shared with `Commands` to reserve ids
+    next_id: Arc<IdReserver>,
+    /// Bitmap of the alive indices, indices past its end are not alive
+    alive: Vec<u8>,
+    /// Bitmap of the free indices, i.e. the indices in `available_ids`
+    free: Vec<u8>,
     entities: Vec<Entity>,
+    /// The current generation of each entity index
+    generations: Vec<u32>,
     /// Flags for entities
     flags: Vec<Vec<u8>>,
-    available_ids: Vec<EntityId>
+    available_ids: Vec<u32>,
+    /// The amount of entities that are alive
+    alive_count: usize,
+}
+
+/// Whether bit `index` of the bitmap is set
+#[inline]
+fn get_bit(bitmap: &[u8], index: u32) -> bool {
+    match bitmap.get(index as usize / 8) {
+        Some(byte) => byte & (1 << (index % 8)) != 0,
+        None => false
+    }
+}
+
+/// Sets bit `index` of the bitmap to `value`, growing the bitmap if needed
+#[inline]
+fn set_bit(bitmap: &mut Vec<u8>, index: u32, value: bool) {
+    let idx = index as usize / 8;
+    if bitmap.len() <= idx {
+        if !value {
+            return;
+        }
+        bitmap.resize(idx + 1, 0);
+    }
+    if value {
+        bitmap[idx] |= 1 << (index % 8);
+    } else {
+        bitmap[idx] &= !(1 << (index % 8));
+    }
+}
+
+/// The next unused entity index, shared between an entity store and the
+/// `Commands` created for it
+pub(crate) struct IdReserver {
+    next_id: AtomicU32,
+    /// The amount of times the world was restored from a snapshot. Indices
+    /// reserved before restoring may be handed out again, so they are only valid
+    /// if they were reserved in the current epoch.
+    epoch: AtomicU32,
+}
+
+impl IdReserver {
+    fn new(next_id: u32) -> Self {
+        Self { next_id: AtomicU32::new(next_id), epoch: AtomicU32::new(0) }
+    }
+    
+    /// Takes an unused index, returning it with the current epoch
+    #[inline]
+    pub(crate) fn reserve(&self) -> (u32, u32) {
+        return (self.next_id.fetch_add(1, Ordering::Relaxed), self.epoch());
+    }
+    
+    #[inline]
+    pub(crate) fn epoch(&self) -> u32 {
+        self.epoch.load(Ordering::Relaxed)
+    }
+    
+    #[inline]
+    fn next_id(&self) -> u32 {
+        self.next_id.load(Ordering::Relaxed)
+    }
+}
+
+impl Clone for EntityStore {
+    /// Clones the store with its own counter to reserve indices
+    fn clone(&self) -> Self {
+        Self {
+            next_id: Arc::new(IdReserver::new(self.next_id.next_id())),
+            alive: self.alive.clone(),
+            free: self.free.clone(),
+            entities: self.entities.clone(),
+            generations: self.generations.clone(),
+            flags: self.flags.clone(),
+            available_ids: self.available_ids.clone(),
+            alive_count: self.alive_count,
+        }
+    }
 }
 
 impl EntityStore {
     pub(crate) fn new() -> Self {
         Self {
-            next_id: 0,
-            dead: Vec::new(),
+            next_id: Arc::new(IdReserver::new(0)),
+            alive: Vec::new(),
+            free: Vec::new(),
             entities: Vec::new(),
+            generations: Vec::new(),
             flags: Vec::new(),
-            available_ids: Vec::new()
+            available_ids: Vec::new(),
+            alive_count: 0,
         }
     }
     
-    /// Gets a new entity id
+    /// Gets a new entity id, reusing the index of a killed entity if possible
     #[inline]
     pub(crate) fn new_id(&mut self) -> EntityId {
-        if let Some(id) = self.available_ids.pop() {
-            // Reset flags
-            let idx = id / 8;
-            let idx2 = id % 8;
-            self.flags.iter_mut().for_each(|flag_bitmap| {
-                if let Some(bitmap) = flag_bitmap.get_mut(idx as usize) {
-                    *bitmap &= !(1 << idx2);
-                }
-            });
-
-            return id;
-        } else {
-            let entity_id = self.next_id;
-            self.next_id += 1;
-            return entity_id;
+        let id = match self.available_ids.pop() {
+            Some(index) => {
+                debug_assert!(get_bit(&self.free, index), "entity index {index} was reused, but not free");
+                set_bit(&mut self.free, index, false);
+                // Reset flags
+                self.flags.iter_mut().for_each(|flag_bitmap| set_bit(flag_bitmap, index, false));
+                EntityId::new(index, self.generations[index as usize])
+            }
+            None => {
+                let (index, _) = self.next_id.reserve();
+                self.grow_to(index);
+                EntityId::new(index, 0)
+            }
+        };
+        self.set_alive(id.index);
+        return id;
+    }
+    
+    /// The counter used to reserve new entity indices
+    #[inline]
+    pub(crate) fn id_reserver(&self) -> &Arc<IdReserver> {
+        &self.next_id
+    }
+    
+    /// Makes sure `index` has a generation. Indices that are skipped because
+    /// they were reserved, but not spawned yet, stay not alive.
+    fn grow_to(&mut self, index: u32) {
+        if self.generations.len() <= index as usize {
+            self.generations.resize(index as usize + 1, 0);
         }
-        
+    }
+    
+    /// Marks an index that is not alive as alive
+    #[inline]
+    fn set_alive(&mut self, index: u32) {
+        debug_assert!(!get_bit(&self.alive, index), "entity index {index} is already alive");
+        set_bit(&mut self.alive, index, true);
+        self.alive_count += 1;
+    }
+    
+    /// Marks an id reserved with `id_reserver` as alive
+    #[inline]
+    pub(crate) fn spawn_reserved(&mut self, ent: EntityId) {
+        debug_assert_eq!(ent.generation, 0);
+        self.grow_to(ent.index);
+        self.set_alive(ent.index);
     }
 
     /// Spawn a new entity with the given ids
     #[inline]
     pub(crate) fn spawn_with_id(&mut self, ent_id: EntityId, arch_id: ArchetypeId, arch_row: ArchRowId) {
-        if self.entities.len() <= ent_id as usize {
-            self.entities.resize_with(ent_id as usize + 1, || Entity { arch_id, arch_row });
+        let index = ent_id.index as usize;
+        if self.entities.len() <= index {
+            self.entities.resize_with(index + 1, || Entity { arch_id, arch_row });
         } else {
-            self.entities[ent_id as usize] = Entity { arch_id, arch_row };
+            self.entities[index] = Entity { arch_id, arch_row };
         }
     }
 
-    /// Marks an entity as dead
+    /// Updates the archetype and row of an existing entity
     #[inline]
-    pub(crate) fn kill_and_keep(&mut self, ent: EntityId) {        
-        let idx = ent / 8;
-        let idx2 = ent % 8;
-        if self.dead.len() <= ent as usize {
-            self.dead.resize(ent as usize + 1, 0);
-        }
-        let dead_map = &mut self.dead[idx as usize];
-        *dead_map |= 1 << idx2
+    pub(crate) fn move_entity(&mut self, ent_id: EntityId, arch_id: ArchetypeId, arch_row: ArchRowId) {
+        self.entities[ent_id.index as usize] = Entity { arch_id, arch_row };
     }
 
+    /// Marks an alive entity as dead and frees its index for reuse with a new generation
     #[inline]
     pub(crate) fn kill(&mut self, ent: EntityId) {
-        self.kill_and_keep(ent);
-        self.free_id(ent);
+        debug_assert!(self.is_alive(ent), "entity {ent} was killed, but is not alive");
+        set_bit(&mut self.alive, ent.index, false);
+        self.alive_count -= 1;
+        let generation = &mut self.generations[ent.index as usize];
+        *generation = generation.wrapping_add(1);
+        self.free_id(ent.index);
     }
     
     #[inline]
-    pub(crate) fn free_id(&mut self, ent: EntityId) {
+    fn free_id(&mut self, ent: u32) {
+        debug_assert!(!get_bit(&self.free, ent), "entity index {ent} was freed twice");
+        set_bit(&mut self.free, ent, true);
         self.available_ids.push(ent);
     }
 
+    /// Whether the entity with the given index is alive
+    #[inline]
+    fn is_index_alive(&self, ent: u32) -> bool {
+        get_bit(&self.alive, ent)
+    }
+
+    /// Whether the entity is alive, `false` for handles of which the index
+    /// has been reused
     #[inline]
     pub(crate) fn is_alive(&self, ent: EntityId) -> bool {
-        let idx = ent / 8;
-        let idx2 = ent % 8;
-        match self.dead.get(idx as usize) {
-            Some(bitmap) => {
-                return bitmap & (1 << idx2) != (1 << idx2);
-            }
-            None => {
-                return true;
-            }
+        match self.generations.get(ent.index as usize) {
+            Some(generation) => *generation == ent.generation && self.is_index_alive(ent.index),
+            None => false
         }
     }
 
     #[inline]
     pub(crate) fn entity_count(&self) -> usize {
-        (0..(self.entities.len() as u32))
-            .filter(|ent_id| self.is_alive(*ent_id))
-            .count()
+        self.alive_count
     }
     
+    /// All alive entities, ordered by index
     #[inline]
-    pub(crate) fn entities(&self) -> &Vec<Entity> {
-        &self.entities
+    pub(crate) fn alive_ids(&self) -> impl std::iter::Iterator<Item = EntityId> + '_ {
+        self.alive.iter()
+            .enumerate()
+            // skips 8 dead entities at once
+            .filter(|(_, byte)| **byte != 0)
+            .flat_map(|(idx, byte)| (0..8)
+                .filter(move |bit| byte & (1 << bit) != 0)
+                .map(move |bit| idx as u32 * 8 + bit))
+            // stops at the last alive entity
+            .take(self.alive_count)
+            .map(|ent_id| EntityId::new(ent_id, self.generations[ent_id as usize]))
+    }
+    
+    /// Returns the entity if it is alive
+    #[inline]
+    pub(crate) fn get(&self, ent: EntityId) -> Option<&Entity> {
+        self.try_get(ent).ok()
+    }
+    
+    /// Returns the entity, or why it can't be accessed
+    #[inline]
+    pub(crate) fn try_get(&self, ent: EntityId) -> Result<&Entity, KiwiError> {
+        let Some(generation) = self.generations.get(ent.index as usize) else {
+            return Err(KiwiError::NoSuchEntity);
+        };
+        if *generation != ent.generation || !self.is_index_alive(ent.index) {
+            return Err(KiwiError::EntityDead);
+        }
+        // an index can be handed out without the entity being spawned yet
+        self.entities.get(ent.index as usize).ok_or(KiwiError::NoSuchEntity)
+    }
+    
+    /// Copies the state of `other`, including the next unused index, so that
+    /// the same ids are handed out again. The counter shared with `Commands`
+    /// starts a new epoch, invalidating the indices reserved before restoring.
+    pub(crate) fn restore_from(&mut self, other: &EntityStore) {
+        self.next_id.next_id.store(other.next_id.next_id(), Ordering::Relaxed);
+        self.next_id.epoch.fetch_add(1, Ordering::Relaxed);
+        self.alive.clone_from(&other.alive);
+        self.free.clone_from(&other.free);
+        self.entities.clone_from(&other.entities);
+        self.generations.clone_from(&other.generations);
+        self.flags.clone_from(&other.flags);
+        self.available_ids.clone_from(&other.available_ids);
+        self.alive_count = other.alive_count;
+    }
+    
+    /// The generation of every entity index
+    #[inline]
+    pub(crate) fn generations(&self) -> &[u32] {
+        &self.generations
+    }
+    
+    /// The indices of killed entities that can be reused
+    #[inline]
+    pub(crate) fn available_ids(&self) -> &[u32] {
+        &self.available_ids
+    }
+    
+    /// The bitmaps of the entities with each flag, indexed by flag id
+    #[inline]
+    pub(crate) fn flags(&self) -> &[Vec<u8>] {
+        &self.flags
+    }
+    
+    /// Creates a store from saved generations, available ids and flags, in
+    /// which only the indices in `alive` are alive. The entities still have to
+    /// be spawned using `spawn_with_id`.
+    ///
+    /// `alive` and `available_ids` should not contain an index twice or have
+    /// indices in common.
+    pub(crate) fn restore(generations: Vec<u32>, alive: &[u32], available_ids: Vec<u32>, flags: Vec<Vec<u8>>) -> Self {
+        let mut store = Self::new();
+        store.next_id.next_id.store(generations.len() as u32, Ordering::Relaxed);
+        store.generations = generations;
+        for index in alive {
+            store.set_alive(*index);
+        }
+        for index in &available_ids {
+            set_bit(&mut store.free, *index, true);
+        }
+        store.available_ids = available_ids;
+        store.flags = flags;
+        return store;
     }
     
     #[inline]
     pub(crate) fn has_flag(&self, ent: EntityId, flag: FlagId) -> bool {
-        let idx = ent / 8;
-        let idx2 = ent % 8;
+        if !self.is_alive(ent) {
+            return false;
+        }
+        let idx = ent.index / 8;
+        let idx2 = ent.index % 8;
         
         match self.flags.get(flag as usize) {
             Some(bitmaps) => {
@@ -133,8 +383,11 @@ impl EntityStore {
     
     #[inline]
     pub(crate) fn set_flag(&mut self, ent: EntityId, flag: FlagId) {
-        let idx = ent / 8;
-        let idx2 = ent % 8;
+        if !self.is_alive(ent) {
+            return;
+        }
+        let idx = ent.index / 8;
+        let idx2 = ent.index % 8;
         
         if self.flags.len() <= flag as usize {
             self.flags.resize_with(flag as usize + 1, Vec::new);
@@ -149,8 +402,11 @@ impl EntityStore {
 
     #[inline]
     pub(crate) fn unset_flag(&mut self, ent: EntityId, flag: FlagId) {
-        let idx = ent / 8;
-        let idx2 = ent % 8;
+        if !self.is_alive(ent) {
+            return;
+        }
+        let idx = ent.index / 8;
+        let idx2 = ent.index % 8;
 
         if self.flags.len() <= flag as usize {
             // flag is already unset
@@ -167,7 +423,7 @@ impl EntityStore {
 
 #[cfg(test)]
 mod tests {
-    use crate::entity::EntityStore;
+    use crate::entity::{EntityStore, EntityId};
     
     #[test]
     fn new_entity_id() {
@@ -175,8 +431,8 @@ mod tests {
         let id1 = ent_store.new_id();
         let id2 = ent_store.new_id();
         
-        assert_eq!(id1, 0);
-        assert_eq!(id2, 1);
+        assert_eq!(id1, EntityId::new(0, 0));
+        assert_eq!(id2, EntityId::new(1, 0));
     }
 
     #[test]
@@ -191,4 +447,128 @@ mod tests {
         assert!(ent_store.is_alive(id1));
         assert!(!ent_store.is_alive(id2));
     }
+    
+    #[test]
+    fn reuse_entity_index() {
+        let mut ent_store = EntityStore::new();
+        let id1 = ent_store.new_id();
+        ent_store.kill(id1);
+        let id2 = ent_store.new_id();
+        
+        assert_eq!(id2.index(), id1.index());
+        assert_ne!(id2.generation(), id1.generation());
+        assert!(!ent_store.is_alive(id1));
+        assert!(ent_store.is_alive(id2));
+    }
+    
+    #[test]
+    fn reserved_entity_ids() {
+        let mut ent_store = EntityStore::new();
+        let reserved = EntityId::new(ent_store.id_reserver().reserve().0, 0);
+        let id = ent_store.new_id();
+        
+        assert_eq!(id, EntityId::new(1, 0));
+        assert!(!ent_store.is_alive(reserved));
+        assert!(ent_store.is_alive(id));
+        
+        ent_store.spawn_reserved(reserved);
+        assert!(ent_store.is_alive(reserved));
+    }
+    
+    #[test]
+    fn dead_bitmap_size() {
+        let mut ent_store = EntityStore::new();
+        let ids: Vec<EntityId> = (0..64).map(|_| ent_store.new_id()).collect();
+        for id in ids {
+            ent_store.kill(id);
+        }
+        
+        assert_eq!(ent_store.alive.len(), 8);
+        assert_eq!(ent_store.free.len(), 8);
+    }
+    
+    /// A xorshift generator, so that the property tests are reproducible
+    struct Rng(u64);
+    
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+    
+    #[test]
+    fn spawn_kill_reserve_interleavings() {
+        for seed in 1..=64u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let mut ent_store = EntityStore::new();
+            let mut alive: Vec<EntityId> = Vec::new();
+            let mut killed: Vec<EntityId> = Vec::new();
+            let mut reserved: Vec<EntityId> = Vec::new();
+            
+            for _ in 0..500 {
+                match rng.below(8) {
+                    0..=2 => {
+                        let id = ent_store.new_id();
+                        assert!(alive.iter().all(|other| other.index() != id.index()), "seed {seed}: index {} handed out twice", id.index());
+                        assert!(!killed.contains(&id), "seed {seed}: id {id} reused with the same generation");
+                        ent_store.spawn_with_id(id, 0, 0);
+                        alive.push(id);
+                    }
+                    3 | 4 if !alive.is_empty() => {
+                        let id = alive.swap_remove(rng.below(alive.len()));
+                        ent_store.kill(id);
+                        killed.push(id);
+                    }
+                    5 => {
+                        let (index, _) = ent_store.id_reserver().reserve();
+                        reserved.push(EntityId::new(index, 0));
+                    }
+                    6 if !reserved.is_empty() => {
+                        let id = reserved.swap_remove(rng.below(reserved.len()));
+                        assert!(!ent_store.is_alive(id));
+                        ent_store.spawn_reserved(id);
+                        ent_store.spawn_with_id(id, 0, 0);
+                        alive.push(id);
+                    }
+                    _ => {}
+                }
+                
+                assert_eq!(ent_store.entity_count(), alive.len(), "seed {seed}");
+                assert!(alive.iter().all(|id| ent_store.is_alive(*id)), "seed {seed}");
+                assert!(killed.iter().all(|id| !ent_store.is_alive(*id)), "seed {seed}");
+                assert!(reserved.iter().all(|id| !ent_store.is_alive(*id)), "seed {seed}");
+            }
+            
+            let mut sorted = alive.clone();
+            sorted.sort_by_key(|id| id.index());
+            assert_eq!(ent_store.alive_ids().collect::<Vec<_>>(), sorted, "seed {seed}");
+            assert!(ent_store.alive.len() <= ent_store.generations.len() / 8 + 1, "seed {seed}");
+        }
+    }
+    
+    #[test]
+    fn alive_entities() {
+        let mut ent_store = EntityStore::new();
+        let ids: Vec<EntityId> = (0..20).map(|_| ent_store.new_id()).collect();
+        for id in &ids {
+            ent_store.spawn_with_id(*id, 0, 0);
+        }
+        for id in &ids[..17] {
+            ent_store.kill(*id);
+        }
+        
+        assert_eq!(ent_store.entity_count(), 3);
+        assert_eq!(ent_store.alive_ids().collect::<Vec<_>>(), &ids[17..]);
+        
+        let id = ent_store.new_id();
+        assert_eq!(ent_store.entity_count(), 4);
+        assert_eq!(ent_store.alive_ids().next(), Some(id));
+    }
 }
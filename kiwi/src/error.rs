@@ -0,0 +1,96 @@
+/// Errors returned by the fallible methods of the [`World`](crate::World)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KiwiError {
+    /// The entity id was never handed out by this world
+    NoSuchEntity,
+    /// The entity has been killed
+    EntityDead,
+    /// The entity doesn't have a component of this type
+    MissingComponent { type_name: &'static str },
+}
+
+impl std::fmt::Display for KiwiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KiwiError::NoSuchEntity => write!(f, "entity does not exist"),
+            KiwiError::EntityDead => write!(f, "entity is not alive"),
+            KiwiError::MissingComponent { type_name } => write!(f, "entity does not have component {}", type_name),
+        }
+    }
+}
+
+impl std::error::Error for KiwiError {}
+
+/// Errors returned when the order of the systems in a [`Schedule`](crate::Schedule)
+/// can't be determined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// The ordering constraints of the systems in a stage form a cycle.
+    /// `systems` contains the labels of the systems in the cycle.
+    Cycle { stage: crate::Stage, systems: Vec<&'static str> },
+    /// A system is ordered before or after a label that no system has
+    UnknownLabel { system: &'static str, label: &'static str },
+}
+
+impl std::fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleError::Cycle { stage, systems } => {
+                write!(f, "the ordering constraints of systems in stage {:?} form a cycle: ", stage)?;
+                for system in systems {
+                    write!(f, "{} -> ", system)?;
+                }
+                write!(f, "{}", systems[0])
+            }
+            ScheduleError::UnknownLabel { system, label } => {
+                write!(f, "system {} is ordered relative to {}, but no system has this label", system, label)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+/// Errors returned when saving or loading a [`World`](crate::World)
+#[derive(Debug)]
+pub enum SerializeError {
+    /// Reading or writing failed
+    Io(std::io::Error),
+    /// The data is not a saved world, or was saved by an incompatible version
+    InvalidFormat,
+    /// The data ended in the middle of a value
+    UnexpectedEnd,
+    /// A value of the given type has an invalid encoding, e.g. a `bool` that is not 0 or 1
+    InvalidValue(&'static str),
+    /// The saved world contains a component that is not registered as serializable.
+    /// Components are registered the first time their id is requested, e.g. when
+    /// they are spawned or by calling `Component::id`.
+    UnknownComponent(String),
+}
+
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializeError::Io(err) => write!(f, "{}", err),
+            SerializeError::InvalidFormat => write!(f, "data is not a saved world"),
+            SerializeError::UnexpectedEnd => write!(f, "unexpected end of data"),
+            SerializeError::InvalidValue(type_name) => write!(f, "invalid value of type {}", type_name),
+            SerializeError::UnknownComponent(name) => write!(f, "component {} is not registered as serializable", name),
+        }
+    }
+}
+
+impl std::error::Error for SerializeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SerializeError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SerializeError {
+    fn from(err: std::io::Error) -> Self {
+        SerializeError::Io(err)
+    }
+}
@@ -0,0 +1,286 @@
+use std::marker::PhantomData;
+
+use crate::resource::Resources;
+use crate::tick::is_newer;
+use crate::world::World;
+
+#[derive(Clone)]
+struct EventInstance<E> {
+    /// Index of the event in all events of this type that were sent
+    id: usize,
+    /// The change tick at which the event was sent
+    tick: u32,
+    event: E,
+}
+
+/// A double-buffered queue of events of type `E`, stored as a resource in the world.
+///
+/// Events are kept until the queue was updated twice, so that every system
+/// running once per update can read them, regardless of whether it runs before
+/// or after the system sending the event. The world updates all queues in
+/// `World::update_events`, which is called at the end of every run of a `Schedule`
+/// and by `World::clear_trackers`.
+#[derive(Clone)]
+pub struct Events<E> {
+    /// Events sent before the last update
+    previous: Vec<EventInstance<E>>,
+    /// Events sent since the last update
+    current: Vec<EventInstance<E>>,
+    /// The number of events sent, which is the id of the next event
+    event_count: usize,
+}
+
+impl<E> Events<E> {
+    pub fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            event_count: 0,
+        }
+    }
+    
+    /// Sends an event at change tick `tick`
+    pub(crate) fn send(&mut self, event: E, tick: u32) {
+        self.current.push(EventInstance { id: self.event_count, tick, event });
+        self.event_count += 1;
+    }
+    
+    /// Drops the events sent before the previous update
+    pub fn update(&mut self) {
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+    }
+    
+    /// Drops all events
+    pub fn clear(&mut self) {
+        self.previous.clear();
+        self.current.clear();
+    }
+    
+    /// The number of events in the queue
+    #[inline]
+    pub fn len(&self) -> usize {
+        return self.previous.len() + self.current.len();
+    }
+    
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+    
+    /// Iterates all events in the queue, in the order they were sent
+    #[inline]
+    pub fn iter(&self) -> EventIter<'_, E> {
+        return EventIter { previous: self.previous.iter(), current: self.current.iter() };
+    }
+    
+    /// The events sent after change tick `last_change_tick`
+    fn iter_since(&self, last_change_tick: u32) -> EventIter<'_, E> {
+        // events are sent in order, so their ticks are sorted
+        return EventIter {
+            previous: self.previous[self.previous.partition_point(|instance| !is_newer(instance.tick, last_change_tick))..].iter(),
+            current: self.current[self.current.partition_point(|instance| !is_newer(instance.tick, last_change_tick))..].iter(),
+        };
+    }
+    
+    /// The events with an id of at least `id`
+    fn iter_from(&self, id: usize) -> EventIter<'_, E> {
+        return EventIter {
+            previous: self.previous[self.previous.partition_point(|instance| instance.id < id)..].iter(),
+            current: self.current[self.current.partition_point(|instance| instance.id < id)..].iter(),
+        };
+    }
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Updates the event queue of one event type
+pub(crate) type EventUpdater = fn(&mut Resources);
+
+/// Updates the event queue of type `E`, if it exists
+pub(crate) fn update_events<E: 'static>(resources: &mut Resources) {
+    if let Some(events) = resources.get_mut::<Events<E>>() {
+        events.update();
+    }
+}
+
+/// Iterator over the events in an event queue
+pub struct EventIter<'a, E> {
+    previous: std::slice::Iter<'a, EventInstance<E>>,
+    current: std::slice::Iter<'a, EventInstance<E>>,
+}
+
+impl<'a, E> EventIter<'a, E> {
+    fn empty() -> Self {
+        return EventIter { previous: [].iter(), current: [].iter() };
+    }
+}
+
+impl<'a, E> Iterator for EventIter<'a, E> {
+    type Item = &'a E;
+    
+    #[inline]
+    fn next(&mut self) -> Option<&'a E> {
+        return self.previous.next()
+            .or_else(|| self.current.next())
+            .map(|instance| &instance.event);
+    }
+    
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.previous.len() + self.current.len();
+        return (len, Some(len));
+    }
+}
+
+impl<'a, E> ExactSizeIterator for EventIter<'a, E> {}
+
+/// The events sent since the current system last ran, returned by
+/// `World::read_events` and passed to systems by `events name: Type`.
+///
+/// Outside of a `Schedule`, these are the events sent since the last call
+/// to `World::clear_trackers`.
+pub struct EventReader<'a, E> {
+    events: Option<&'a Events<E>>,
+    last_change_tick: u32,
+}
+
+impl<'a, E> EventReader<'a, E> {
+    #[doc(hidden)]
+    #[inline]
+    pub fn new(events: Option<&'a Events<E>>, last_change_tick: u32) -> Self {
+        Self { events, last_change_tick }
+    }
+    
+    #[inline]
+    pub fn iter(&self) -> EventIter<'a, E> {
+        return match self.events {
+            Some(events) => events.iter_since(self.last_change_tick),
+            None => EventIter::empty(),
+        };
+    }
+    
+    #[inline]
+    pub fn len(&self) -> usize {
+        return self.iter().len();
+    }
+    
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+}
+
+impl<'a, E> Clone for EventReader<'a, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, E> Copy for EventReader<'a, E> {}
+
+impl<'a, E> IntoIterator for EventReader<'a, E> {
+    type Item = &'a E;
+    type IntoIter = EventIter<'a, E>;
+    
+    #[inline]
+    fn into_iter(self) -> EventIter<'a, E> {
+        return self.iter();
+    }
+}
+
+/// Sends events from a system, passed to systems by `events mut name: Type`
+pub struct EventWriter<'a, E> {
+    events: &'a mut Events<E>,
+    tick: u32,
+}
+
+impl<'a, E: 'static> EventWriter<'a, E> {
+    /// # Panics
+    /// If the event type wasn't added to the world
+    #[doc(hidden)]
+    #[inline]
+    pub fn new(events: Option<&'a mut Events<E>>, tick: u32) -> Self {
+        let events = events.unwrap_or_else(|| panic!(
+            "Event {0} was not added to the world. Use `world.add_event::<{0}>()` first.",
+            std::any::type_name::<E>()
+        ));
+        Self { events, tick }
+    }
+    
+    #[inline]
+    pub fn send(&mut self, event: E) {
+        self.events.send(event, self.tick);
+    }
+}
+
+/// A cursor into the event queue of type `E`, returning every event once.
+///
+/// Unlike `World::read_events`, every cursor keeps track of the events it read,
+/// independent of the change ticks of the world.
+pub struct EventCursor<E> {
+    /// The id of the first event that wasn't read yet
+    next_id: usize,
+    _marker: PhantomData<fn() -> E>,
+}
+
+impl<E: 'static> EventCursor<E> {
+    pub fn new() -> Self {
+        Self { next_id: 0, _marker: PhantomData }
+    }
+    
+    /// Returns the events sent since the last read. Events are missed if the
+    /// queue was updated twice since then.
+    pub fn read<'a>(&mut self, world: &'a World) -> EventIter<'a, E> {
+        return match world.resource::<Events<E>>() {
+            Some(events) => {
+                let iter = events.iter_from(self.next_id);
+                self.next_id = events.event_count;
+                iter
+            },
+            None => EventIter::empty(),
+        };
+    }
+}
+
+impl<E: 'static> Default for EventCursor<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn double_buffered() {
+        let mut events = Events::new();
+        events.send(0, 1);
+        events.update();
+        events.send(1, 2);
+        assert_eq!(events.iter().copied().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(events.iter_since(1).copied().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(events.iter_from(1).copied().collect::<Vec<_>>(), vec![1]);
+        events.update();
+        assert_eq!(events.iter().copied().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(events.iter_from(0).len(), 1);
+        events.update();
+        assert!(events.is_empty());
+    }
+    
+    #[test]
+    fn wrapping_ticks() {
+        let mut events = Events::new();
+        events.send(0, u32::MAX - 1);
+        events.send(1, u32::MAX);
+        events.send(2, 0);
+        events.send(3, 1);
+        assert_eq!(events.iter_since(u32::MAX - 1).copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(events.iter_since(0).copied().collect::<Vec<_>>(), vec![3]);
+    }
+}
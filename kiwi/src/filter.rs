@@ -0,0 +1,115 @@
+use crate::arch::{Archetype, ArchRowId};
+use crate::component::{Component, ComponentId, Flag, FlagId};
+use crate::entity::EntityStore;
+use crate::tick::is_newer;
+
+/// A filter for the entities returned by a query.
+///
+/// Filters are usually created by the `query!` and `query_mut!` macros and
+/// the `system` attribute, e.g. `query!(world, Pos, without Frozen, flag Flags::Enemy)`.
+///
+/// `Added` and `Changed` report the changes made after the world's
+/// [`last_change_tick`](crate::World::last_change_tick).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// Only entities that have the component
+    With(ComponentId),
+    /// Only entities that don't have the component
+    Without(ComponentId),
+    /// Only entities that have the flag set
+    Flag(FlagId),
+    /// Only entities of which the component was added recently
+    Added(ComponentId),
+    /// Only entities of which the component was added or mutably accessed recently
+    Changed(ComponentId),
+    /// Entities that match any of the filters
+    Or(Vec<Filter>),
+}
+
+impl Filter {
+    #[inline]
+    pub fn with<T: Component>() -> Self {
+        Filter::With(T::id())
+    }
+    
+    #[inline]
+    pub fn without<T: Component>() -> Self {
+        Filter::Without(T::id())
+    }
+    
+    #[inline]
+    pub fn flag<F: Flag>(flag: F) -> Self {
+        Filter::Flag(flag.into())
+    }
+    
+    #[inline]
+    pub fn added<T: Component>() -> Self {
+        Filter::Added(T::id())
+    }
+    
+    #[inline]
+    pub fn changed<T: Component>() -> Self {
+        Filter::Changed(T::id())
+    }
+    
+    #[inline]
+    pub fn or(filters: Vec<Filter>) -> Self {
+        Filter::Or(filters)
+    }
+    
+    /// Whether entities in the archetype can match the filter. Flags are
+    /// not checked here, as they are stored per entity.
+    fn matches_archetype(&self, archetype: &Archetype) -> bool {
+        match self {
+            Filter::With(id) | Filter::Added(id) | Filter::Changed(id) => archetype.has_component(*id),
+            Filter::Without(id) => !archetype.has_component(*id),
+            Filter::Flag(_) => true,
+            Filter::Or(filters) => filters.iter().any(|filter| filter.matches_archetype(archetype)),
+        }
+    }
+    
+    /// Whether the filter needs to be checked for every entity
+    fn checks_rows(&self) -> bool {
+        match self {
+            Filter::With(_) | Filter::Without(_) => false,
+            Filter::Flag(_) | Filter::Added(_) | Filter::Changed(_) => true,
+            Filter::Or(filters) => filters.iter().any(|filter| filter.checks_rows()),
+        }
+    }
+    
+    fn matches_row(&self, archetype: &Archetype, entity_store: &EntityStore, last_change_tick: u32, row: ArchRowId) -> bool {
+        match self {
+            Filter::With(_) | Filter::Without(_) => self.matches_archetype(archetype),
+            Filter::Flag(flag) => entity_store.has_flag(archetype.get_entity_id(row), *flag),
+            Filter::Added(id) => archetype.component_ticks(*id)
+                .is_some_and(|ticks| is_newer(ticks[row as usize].added, last_change_tick)),
+            Filter::Changed(id) => archetype.component_ticks(*id)
+                .is_some_and(|ticks| is_newer(ticks[row as usize].changed(), last_change_tick)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.matches_row(archetype, entity_store, last_change_tick, row)),
+        }
+    }
+}
+
+/// Whether entities in the archetype can match all filters
+#[inline]
+pub(crate) fn matches_archetype(filters: &[Filter], archetype: &Archetype) -> bool {
+    filters.iter().all(|filter| filter.matches_archetype(archetype))
+}
+
+/// Whether the entity in the given row matches all filters. Changes made after
+/// `last_change_tick` are reported by `Added` and `Changed` filters.
+#[inline]
+pub(crate) fn matches_row(filters: &[Filter], archetype: &Archetype, entity_store: &EntityStore, last_change_tick: u32, row: ArchRowId) -> bool {
+    filters.iter().all(|filter| filter.matches_row(archetype, entity_store, last_change_tick, row))
+}
+
+/// The rows of the archetype whose entities match all filters
+pub(crate) fn filter_rows(filters: &[Filter], archetype: &Archetype, entity_store: &EntityStore, last_change_tick: u32) -> Vec<ArchRowId> {
+    if !filters.iter().any(|filter| filter.checks_rows()) {
+        return archetype.get_arch_rows().collect();
+    }
+    
+    archetype.get_arch_rows()
+        .filter(|row| matches_row(filters, archetype, entity_store, last_change_tick, *row))
+        .collect()
+}
@@ -7,6 +7,7 @@ mod macros {
     pub use kiwi_macros::query_mut;
     pub use kiwi_macros::spawn_entity;
     pub use kiwi_macros::Component;
+    pub use kiwi_macros::Serializable;
     pub use kiwi_macros::flags;
 }
 pub use macros::*;
@@ -17,8 +18,52 @@ pub use world::World;
 mod entity;
 pub use entity::EntityId;
 
+mod error;
+pub use error::{KiwiError, ScheduleError, SerializeError};
+
 mod component;
-pub use component::{ComponentId, Component, Flag, FlagId};
+pub use component::{ComponentId, Component, Flag, FlagId, component_id};
+#[doc(hidden)]
+pub use component::component_id_with;
+
+mod filter;
+pub use filter::Filter;
+
+mod query;
+pub use query::{Read, Write, ChunkQuery, ReadOnlyChunkQuery, QueryIter};
+#[doc(hidden)]
+pub use query::{QueryComponent, ReadOnlyQueryComponent, QueryRows, FetchRows};
+
+mod par;
+
+mod resource;
+
+mod serialize;
+pub use serialize::Serializable;
+#[doc(hidden)]
+pub use serialize::register_serializable;
+
+mod snapshot;
+pub use snapshot::WorldSnapshot;
+#[doc(hidden)]
+pub use snapshot::register_clone;
+
+mod removed;
+mod tick;
+
+mod event;
+pub use event::{Events, EventIter, EventReader, EventWriter, EventCursor};
+
+mod commands;
+pub use commands::Commands;
+
+mod system;
+pub use system::{SystemAccess, SystemInfo};
+#[doc(hidden)]
+pub use system::{SendSyncCheck, IsSendSync, NotSendSync};
+
+mod schedule;
+pub use schedule::{Schedule, Stage, SystemConfig, IntoSystem};
 
 mod archetype;
 mod arch_store;
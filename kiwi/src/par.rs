@@ -0,0 +1,99 @@
+use std::sync::Mutex;
+
+use crate::arch::Archetype;
+use crate::query::{FetchRows, QueryIter, QueryRows};
+
+/// Queries with fewer rows than this per thread are split into fewer chunks
+const MIN_CHUNK_SIZE: usize = 1024;
+
+impl<'a, F, R> QueryIter<'a, F, R>
+where
+    F: FetchRows<'a, R> + Sync,
+    F::Item: Send,
+    R: QueryRows + Send,
+{
+    /// Calls `f` on every item, spreading the items over the available threads.
+    ///
+    /// The rows of each matched archetype are split into chunks of consecutive
+    /// rows, and each thread fetches the components of the chunks it runs
+    /// itself, so every row is visited exactly once. Queries with few rows run
+    /// on the current thread.
+    pub fn par_for_each<G>(self, f: G)
+    where
+        G: Fn(F::Item) + Sync,
+    {
+        let threads = available_threads();
+        let chunk_size = self.remaining_rows().div_ceil(threads).max(MIN_CHUNK_SIZE);
+        self.run_chunks(chunk_size, threads, &f);
+    }
+    
+    /// Calls `f` on every item, running chunks of at most `chunk_size`
+    /// consecutive rows of an archetype on at most as many threads as are
+    /// available
+    ///
+    /// # Panics
+    /// If `chunk_size` is 0
+    pub fn par_for_each_chunked<G>(self, chunk_size: usize, f: G)
+    where
+        G: Fn(F::Item) + Sync,
+    {
+        assert!(chunk_size > 0, "chunk_size should be greater than 0");
+        self.run_chunks(chunk_size, available_threads(), &f);
+    }
+    
+    /// Splits the rows of the archetypes into chunks of `chunk_size` rows,
+    /// and runs the chunks on at most `threads` threads, including the current
+    /// thread
+    fn run_chunks<G>(self, chunk_size: usize, threads: usize, f: &G)
+    where
+        G: Fn(F::Item) + Sync,
+    {
+        let QueryIter { archetypes, fetch, current } = self;
+        // the rest of the archetype that was being iterated
+        if let Some(items) = current {
+            items.for_each(f);
+        }
+        
+        let mut chunks = Vec::new();
+        for (archetype, mut rows) in archetypes {
+            while rows.len() > 0 {
+                chunks.push(Chunk(archetype, rows.split_front(chunk_size)));
+            }
+        }
+        
+        let run = |Chunk(archetype, rows): Chunk<'a, R>| fetch.fetch(archetype, rows).for_each(f);
+        let workers = threads.min(chunks.len()).saturating_sub(1);
+        if workers == 0 {
+            chunks.into_iter().for_each(run);
+            return;
+        }
+        
+        let chunks = Mutex::new(chunks.into_iter());
+        // the lock is released before the chunk is run
+        let next = || chunks.lock().unwrap().next();
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while let Some(chunk) = next() {
+                        run(chunk);
+                    }
+                });
+            }
+            while let Some(chunk) = next() {
+                run(chunk);
+            }
+        });
+    }
+}
+
+#[inline]
+fn available_threads() -> usize {
+    return std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+}
+
+/// Consecutive rows of an archetype, run on one thread
+struct Chunk<'a, R>(&'a Archetype, R);
+
+// The rows of the chunks don't overlap, and the components are only accessed
+// through the items, which are `Send`. The archetype itself is only read.
+unsafe impl<R: Send> Send for Chunk<'_, R> {}
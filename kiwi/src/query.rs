@@ -0,0 +1,504 @@
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::ptr::NonNull;
+
+use crate::arch::{Archetype, ArchRowId};
+use crate::component::{Component, ComponentId};
+use crate::entity::EntityId;
+
+/// Requests shared access to component `T` in a mutable query.
+///
+/// This is what `&T` expands to in `query!`, `query_mut!` and systems.
+pub struct Read<T>(PhantomData<T>);
+
+/// Requests mutable access to component `T` in a query.
+///
+/// This is what `&mut T` expands to in `query!`, `query_mut!` and systems.
+pub struct Write<T>(PhantomData<T>);
+
+/// A type that can be requested in a mutable query: a component `T`, `Read<T>`,
+/// `Write<T>`, or one of those wrapped in an `Option`. Only used internally
+#[doc(hidden)]
+pub trait QueryComponent {
+    /// The item returned by mutable queries
+    type ItemMut<'a>;
+    
+    /// The slice of components returned by mutable chunk queries
+    type SliceMut<'a>;
+    
+    /// The id of the component
+    fn component_id() -> ComponentId;
+    
+    /// The component an entity needs to have to match the query, `None` if
+    /// the component is optional
+    fn required() -> Option<ComponentId>;
+    
+    /// Whether the component is written to by mutable queries
+    fn writes() -> bool;
+    
+    /// # Safety
+    /// `component` should point to an initialised component, or be `None` if
+    /// the component is optional and the entity doesn't have it. The component
+    /// should not be aliased if it is written to.
+    unsafe fn fetch_mut<'a>(component: Option<NonNull<u8>>) -> Self::ItemMut<'a>;
+    
+    /// # Safety
+    /// `column` should point to `len` initialised components, or be `None` if
+    /// the component is optional and the archetype doesn't have it. The
+    /// components should not be aliased if they are written to.
+    unsafe fn fetch_slice_mut<'a>(column: Option<NonNull<u8>>, len: usize) -> Self::SliceMut<'a>;
+}
+
+/// A type that can be requested in a shared query. Only used internally
+#[doc(hidden)]
+pub trait ReadOnlyQueryComponent: QueryComponent {
+    /// The item returned by shared queries
+    type Item<'a>;
+    
+    /// The slice of components returned by shared chunk queries
+    type Slice<'a>;
+    
+    /// # Safety
+    /// `component` should point to an initialised component, or be `None` if
+    /// the component is optional and the entity doesn't have it
+    unsafe fn fetch<'a>(component: Option<NonNull<u8>>) -> Self::Item<'a>;
+    
+    /// # Safety
+    /// `column` should point to `len` initialised components, or be `None` if
+    /// the component is optional and the archetype doesn't have it
+    unsafe fn fetch_slice<'a>(column: Option<NonNull<u8>>, len: usize) -> Self::Slice<'a>;
+}
+
+impl<T: Component + 'static> QueryComponent for T {
+    type ItemMut<'a> = &'a mut T;
+    type SliceMut<'a> = &'a mut [T];
+    
+    #[inline]
+    fn component_id() -> ComponentId {
+        T::id()
+    }
+    
+    #[inline]
+    fn required() -> Option<ComponentId> {
+        Some(T::id())
+    }
+    
+    #[inline]
+    fn writes() -> bool {
+        true
+    }
+    
+    #[inline]
+    unsafe fn fetch_mut<'a>(component: Option<NonNull<u8>>) -> &'a mut T {
+        component.unwrap_unchecked().cast::<T>().as_mut()
+    }
+    
+    #[inline]
+    unsafe fn fetch_slice_mut<'a>(column: Option<NonNull<u8>>, len: usize) -> &'a mut [T] {
+        std::slice::from_raw_parts_mut(column.unwrap_unchecked().cast::<T>().as_ptr(), len)
+    }
+}
+
+impl<T: Component + 'static> ReadOnlyQueryComponent for T {
+    type Item<'a> = &'a T;
+    type Slice<'a> = &'a [T];
+    
+    #[inline]
+    unsafe fn fetch<'a>(component: Option<NonNull<u8>>) -> &'a T {
+        component.unwrap_unchecked().cast::<T>().as_ref()
+    }
+    
+    #[inline]
+    unsafe fn fetch_slice<'a>(column: Option<NonNull<u8>>, len: usize) -> &'a [T] {
+        std::slice::from_raw_parts(column.unwrap_unchecked().cast::<T>().as_ptr(), len)
+    }
+}
+
+impl<T: Component + 'static> QueryComponent for Read<T> {
+    type ItemMut<'a> = &'a T;
+    type SliceMut<'a> = &'a [T];
+    
+    #[inline]
+    fn component_id() -> ComponentId {
+        T::id()
+    }
+    
+    #[inline]
+    fn required() -> Option<ComponentId> {
+        Some(T::id())
+    }
+    
+    #[inline]
+    fn writes() -> bool {
+        false
+    }
+    
+    #[inline]
+    unsafe fn fetch_mut<'a>(component: Option<NonNull<u8>>) -> &'a T {
+        component.unwrap_unchecked().cast::<T>().as_ref()
+    }
+    
+    #[inline]
+    unsafe fn fetch_slice_mut<'a>(column: Option<NonNull<u8>>, len: usize) -> &'a [T] {
+        Self::fetch_slice(column, len)
+    }
+}
+
+impl<T: Component + 'static> ReadOnlyQueryComponent for Read<T> {
+    type Item<'a> = &'a T;
+    type Slice<'a> = &'a [T];
+    
+    #[inline]
+    unsafe fn fetch<'a>(component: Option<NonNull<u8>>) -> &'a T {
+        component.unwrap_unchecked().cast::<T>().as_ref()
+    }
+    
+    #[inline]
+    unsafe fn fetch_slice<'a>(column: Option<NonNull<u8>>, len: usize) -> &'a [T] {
+        std::slice::from_raw_parts(column.unwrap_unchecked().cast::<T>().as_ptr(), len)
+    }
+}
+
+impl<T: Component + 'static> QueryComponent for Write<T> {
+    type ItemMut<'a> = &'a mut T;
+    type SliceMut<'a> = &'a mut [T];
+    
+    #[inline]
+    fn component_id() -> ComponentId {
+        T::id()
+    }
+    
+    #[inline]
+    fn required() -> Option<ComponentId> {
+        Some(T::id())
+    }
+    
+    #[inline]
+    fn writes() -> bool {
+        true
+    }
+    
+    #[inline]
+    unsafe fn fetch_mut<'a>(component: Option<NonNull<u8>>) -> &'a mut T {
+        component.unwrap_unchecked().cast::<T>().as_mut()
+    }
+    
+    #[inline]
+    unsafe fn fetch_slice_mut<'a>(column: Option<NonNull<u8>>, len: usize) -> &'a mut [T] {
+        std::slice::from_raw_parts_mut(column.unwrap_unchecked().cast::<T>().as_ptr(), len)
+    }
+}
+
+impl<Q: QueryComponent> QueryComponent for Option<Q> {
+    type ItemMut<'a> = Option<Q::ItemMut<'a>>;
+    type SliceMut<'a> = Option<Q::SliceMut<'a>>;
+    
+    #[inline]
+    fn component_id() -> ComponentId {
+        Q::component_id()
+    }
+    
+    #[inline]
+    fn required() -> Option<ComponentId> {
+        None
+    }
+    
+    #[inline]
+    fn writes() -> bool {
+        Q::writes()
+    }
+    
+    #[inline]
+    unsafe fn fetch_mut<'a>(component: Option<NonNull<u8>>) -> Option<Q::ItemMut<'a>> {
+        component.map(|ptr| Q::fetch_mut(Some(ptr)))
+    }
+    
+    #[inline]
+    unsafe fn fetch_slice_mut<'a>(column: Option<NonNull<u8>>, len: usize) -> Option<Q::SliceMut<'a>> {
+        column.map(|ptr| Q::fetch_slice_mut(Some(ptr), len))
+    }
+}
+
+impl<Q: ReadOnlyQueryComponent> ReadOnlyQueryComponent for Option<Q> {
+    type Item<'a> = Option<Q::Item<'a>>;
+    type Slice<'a> = Option<Q::Slice<'a>>;
+    
+    #[inline]
+    unsafe fn fetch<'a>(component: Option<NonNull<u8>>) -> Option<Q::Item<'a>> {
+        component.map(|ptr| Q::fetch(Some(ptr)))
+    }
+    
+    #[inline]
+    unsafe fn fetch_slice<'a>(column: Option<NonNull<u8>>, len: usize) -> Option<Q::Slice<'a>> {
+        column.map(|ptr| Q::fetch_slice(Some(ptr), len))
+    }
+}
+
+/// Get the items of type `Q` for the given rows of the archetype
+///
+/// # Safety
+/// The archetype should contain the component if it is not optional
+#[inline]
+pub(crate) unsafe fn fetch_all<'a, Q: ReadOnlyQueryComponent>(
+    archetype: &'a Archetype,
+    rows: impl std::iter::Iterator<Item = ArchRowId> + 'a
+) -> impl std::iter::Iterator<Item = Q::Item<'a>> + 'a {
+    let column = archetype.column_ptr(Q::component_id());
+    rows.map(move |row| {
+        Q::fetch(column.map(|(ptr, size)| ptr.add(row as usize * size)))
+    })
+}
+
+/// Get the mutable items of type `Q` for the given rows of the archetype. If
+/// `Q` is written to, the components are marked as changed at `change_tick`.
+///
+/// # Safety
+/// The archetype should contain the component if it is not optional, and no
+/// other references to the components may exist while the items are in use.
+/// The components are stored behind pointers in the archetype, so a shared
+/// reference to the archetype suffices.
+#[inline]
+pub(crate) unsafe fn fetch_all_mut<'a, Q: QueryComponent>(
+    archetype: &'a Archetype,
+    rows: impl std::iter::Iterator<Item = ArchRowId> + 'a,
+    change_tick: u32
+) -> impl std::iter::Iterator<Item = Q::ItemMut<'a>> + 'a {
+    let column = archetype.column_ptr(Q::component_id());
+    let ticks = if Q::writes() { archetype.component_ticks(Q::component_id()) } else { None };
+    rows.map(move |row| {
+        if let Some(ticks) = ticks {
+            ticks[row as usize].set_changed(change_tick);
+        }
+        Q::fetch_mut(column.map(|(ptr, size)| ptr.add(row as usize * size)))
+    })
+}
+
+/// The rows of an archetype a query iterates: all rows, or the rows matching the
+/// filters of the query. Only used internally
+#[doc(hidden)]
+pub trait QueryRows: ExactSizeIterator<Item = ArchRowId> + Clone {
+    /// Splits off the first `n` rows
+    fn split_front(&mut self, n: usize) -> Self;
+}
+
+impl QueryRows for Range<ArchRowId> {
+    #[inline]
+    fn split_front(&mut self, n: usize) -> Self {
+        let mid = self.start + n.min(self.len()) as ArchRowId;
+        let front = self.start..mid;
+        self.start = mid;
+        return front;
+    }
+}
+
+impl QueryRows for std::vec::IntoIter<ArchRowId> {
+    #[inline]
+    fn split_front(&mut self, n: usize) -> Self {
+        let front: Vec<ArchRowId> = self.by_ref().take(n).collect();
+        return front.into_iter();
+    }
+}
+
+/// Fetches the items of a query for rows of an archetype. Implemented by the
+/// closures of the generated query functions. Only used internally
+#[doc(hidden)]
+pub trait FetchRows<'a, R> {
+    type Item;
+    type Iter: Iterator<Item = Self::Item>;
+    
+    fn fetch(&self, archetype: &'a Archetype, rows: R) -> Self::Iter;
+}
+
+impl<'a, R, I: Iterator, F: Fn(&'a Archetype, R) -> I> FetchRows<'a, R> for F {
+    type Item = I::Item;
+    type Iter = I;
+    
+    #[inline]
+    fn fetch(&self, archetype: &'a Archetype, rows: R) -> I {
+        self(archetype, rows)
+    }
+}
+
+/// The iterator returned by `query!`, `query_mut!` and the query functions of
+/// the world. Yields the items of the matched rows one archetype at a time.
+///
+/// Can also be iterated on multiple threads using `par_for_each`.
+pub struct QueryIter<'a, F: FetchRows<'a, R>, R> {
+    /// The matched archetypes and their rows that haven't been iterated yet
+    pub(crate) archetypes: std::vec::IntoIter<(&'a Archetype, R)>,
+    pub(crate) fetch: F,
+    /// The items of the archetype that is being iterated
+    pub(crate) current: Option<F::Iter>,
+}
+
+impl<'a, F: FetchRows<'a, R>, R: QueryRows> QueryIter<'a, F, R> {
+    #[inline]
+    pub(crate) fn new(archetypes: Vec<(&'a Archetype, R)>, fetch: F) -> Self {
+        return QueryIter { archetypes: archetypes.into_iter(), fetch, current: None };
+    }
+    
+    /// The number of rows in the archetypes that haven't been iterated yet
+    #[inline]
+    pub(crate) fn remaining_rows(&self) -> usize {
+        self.archetypes.as_slice().iter().map(|(_, rows)| rows.len()).sum()
+    }
+}
+
+impl<'a, F: FetchRows<'a, R>, R: QueryRows> Iterator for QueryIter<'a, F, R> {
+    type Item = F::Item;
+    
+    #[inline]
+    fn next(&mut self) -> Option<F::Item> {
+        loop {
+            if let Some(item) = self.current.as_mut().and_then(|items| items.next()) {
+                return Some(item);
+            }
+            let (archetype, rows) = self.archetypes.next()?;
+            self.current = Some(self.fetch.fetch(archetype, rows));
+        }
+    }
+    
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.current.as_ref().map_or((0, Some(0)), |items| items.size_hint());
+        let rows = self.remaining_rows();
+        return (lower + rows, upper.map(|upper| upper + rows));
+    }
+    
+    #[inline]
+    fn fold<B, G: FnMut(B, F::Item) -> B>(self, init: B, mut g: G) -> B {
+        let mut acc = init;
+        if let Some(items) = self.current {
+            acc = items.fold(acc, &mut g);
+        }
+        for (archetype, rows) in self.archetypes {
+            acc = self.fetch.fetch(archetype, rows).fold(acc, &mut g);
+        }
+        return acc;
+    }
+}
+
+/// Panics if a component that is written to is requested more than once in a
+/// mutable query, which would create multiple references to the same component
+/// of which one is mutable. `components` contains the id of each component and
+/// whether it is written to.
+#[inline]
+pub(crate) fn assert_unique_components(components: &[(ComponentId, bool)]) {
+    for (i, (id, writes)) in components.iter().enumerate() {
+        let conflict = components[..i].iter()
+            .any(|(other_id, other_writes)| other_id == id && (*writes || *other_writes));
+        if conflict {
+            panic!(
+                "Component {} is requested more than once in a mutable query, which would create multiple references to the same component of which one is mutable",
+                crate::component::component_name(*id).unwrap_or("<unknown>")
+            );
+        }
+    }
+}
+
+/// A tuple of components that can be requested in `World::query_chunks_mut`,
+/// e.g. `(Read<Position>, Velocity)`. Components are requested like in
+/// mutable queries: `T` and `Write<T>` yield `&mut [T]`, `Read<T>` yields `&[T]`
+/// and `Option<Q>` yields `None` for archetypes without the component.
+pub trait ChunkQuery {
+    /// The entity ids and component slices of one archetype
+    type ChunkMut<'a>;
+    
+    /// The id of each component and whether it is written to
+    fn components() -> Vec<(ComponentId, bool)>;
+    
+    /// The components an entity needs to have to match the query, `None` for
+    /// optional components
+    fn required() -> Vec<Option<ComponentId>>;
+    
+    /// # Safety
+    /// `columns` should contain a pointer to the components of each entity for
+    /// every component in `components`, in the same order. The components
+    /// should not be aliased if they are written to.
+    #[doc(hidden)]
+    unsafe fn fetch_chunk_mut<'a>(entities: &'a [EntityId], columns: &[Option<NonNull<u8>>]) -> Self::ChunkMut<'a>;
+}
+
+/// A tuple of components that can be requested in `World::query_chunks`,
+/// e.g. `(Position, Option<Velocity>)`. `T` and `Read<T>` yield `&[T]`.
+pub trait ReadOnlyChunkQuery: ChunkQuery {
+    /// The entity ids and component slices of one archetype
+    type Chunk<'a>;
+    
+    /// # Safety
+    /// `columns` should contain a pointer to the components of each entity for
+    /// every component in `components`, in the same order
+    #[doc(hidden)]
+    unsafe fn fetch_chunk<'a>(entities: &'a [EntityId], columns: &[Option<NonNull<u8>>]) -> Self::Chunk<'a>;
+}
+
+macro_rules! impl_chunk_query {
+    ($($name:ident),*) => {
+        impl<$($name: QueryComponent + 'static),*> ChunkQuery for ($($name,)*) {
+            type ChunkMut<'a> = (&'a [EntityId], $($name::SliceMut<'a>,)*);
+            
+            #[inline]
+            fn components() -> Vec<(ComponentId, bool)> {
+                vec![$(($name::component_id(), $name::writes()),)*]
+            }
+            
+            #[inline]
+            fn required() -> Vec<Option<ComponentId>> {
+                vec![$($name::required(),)*]
+            }
+            
+            #[inline]
+            unsafe fn fetch_chunk_mut<'a>(entities: &'a [EntityId], columns: &[Option<NonNull<u8>>]) -> Self::ChunkMut<'a> {
+                let mut columns = columns.iter();
+                return (entities, $($name::fetch_slice_mut(*columns.next().unwrap_unchecked(), entities.len()),)*);
+            }
+        }
+        
+        impl<$($name: ReadOnlyQueryComponent + 'static),*> ReadOnlyChunkQuery for ($($name,)*) {
+            type Chunk<'a> = (&'a [EntityId], $($name::Slice<'a>,)*);
+            
+            #[inline]
+            unsafe fn fetch_chunk<'a>(entities: &'a [EntityId], columns: &[Option<NonNull<u8>>]) -> Self::Chunk<'a> {
+                let mut columns = columns.iter();
+                return (entities, $($name::fetch_slice(*columns.next().unwrap_unchecked(), entities.len()),)*);
+            }
+        }
+    };
+}
+
+impl_chunk_query!(A);
+impl_chunk_query!(A, B);
+impl_chunk_query!(A, B, C);
+impl_chunk_query!(A, B, C, D);
+impl_chunk_query!(A, B, C, D, E);
+impl_chunk_query!(A, B, C, D, E, F);
+impl_chunk_query!(A, B, C, D, E, F, G);
+impl_chunk_query!(A, B, C, D, E, F, G, H);
+
+/// The pointers to the first component of each of the `components` in the
+/// archetype, `None` for components the archetype doesn't contain
+#[inline]
+pub(crate) fn chunk_columns(archetype: &Archetype, components: &[(ComponentId, bool)]) -> Vec<Option<NonNull<u8>>> {
+    components.iter()
+        .map(|(id, _)| archetype.column_ptr(*id).map(|(ptr, _)| ptr))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::QueryRows;
+    
+    #[test]
+    fn split_rows() {
+        let mut rows = 0..10;
+        assert_eq!(rows.split_front(4), 0..4);
+        assert_eq!(rows.split_front(4), 4..8);
+        assert_eq!(rows.split_front(4), 8..10);
+        assert_eq!(rows.len(), 0);
+        
+        let mut rows = vec![1, 3, 5].into_iter();
+        assert_eq!(rows.split_front(2).collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(rows.split_front(2).collect::<Vec<_>>(), vec![5]);
+        assert_eq!(rows.len(), 0);
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::component::ComponentId;
+use crate::entity::EntityId;
+use crate::tick::is_newer;
+
+/// Keeps track of the components that were removed from entities and of the
+/// entities that were killed. Every removal is stored with the change tick it
+/// happened at, so that it can be reported to the systems that didn't see it yet.
+#[derive(Clone)]
+pub(crate) struct Removals {
+    components: HashMap<ComponentId, Vec<(EntityId, u32)>>,
+    despawned: Vec<(EntityId, u32)>,
+}
+
+impl Removals {
+    pub(crate) fn new() -> Self {
+        Self {
+            components: HashMap::new(),
+            despawned: Vec::new(),
+        }
+    }
+    
+    #[inline]
+    pub(crate) fn component_removed(&mut self, component: ComponentId, entity: EntityId, tick: u32) {
+        self.components.entry(component).or_default().push((entity, tick));
+    }
+    
+    #[inline]
+    pub(crate) fn despawned(&mut self, entity: EntityId, tick: u32) {
+        self.despawned.push((entity, tick));
+    }
+    
+    /// The entities that lost the component after `last_change_tick`
+    pub(crate) fn removed_since(&self, component: ComponentId, last_change_tick: u32) -> impl Iterator<Item = EntityId> + '_ {
+        self.components.get(&component)
+            .map(|removed| removed.as_slice())
+            .unwrap_or(&[])
+            .iter()
+            .filter(move |(_, tick)| is_newer(*tick, last_change_tick))
+            .map(|(entity, _)| *entity)
+    }
+    
+    /// The entities that were killed after `last_change_tick`
+    pub(crate) fn despawned_since(&self, last_change_tick: u32) -> impl Iterator<Item = EntityId> + '_ {
+        self.despawned.iter()
+            .filter(move |(_, tick)| is_newer(*tick, last_change_tick))
+            .map(|(entity, _)| *entity)
+    }
+    
+    /// Adds the removals of `other` that aren't in this log. They happened before
+    /// the removals in this log, so they are added in front of them.
+    pub(crate) fn merge(&mut self, other: &Removals) {
+        for (component, removed) in &other.components {
+            merge_log(self.components.entry(*component).or_default(), removed);
+        }
+        merge_log(&mut self.despawned, &other.despawned);
+    }
+    
+    /// Forgets the removals that happened at or before `tick`
+    pub(crate) fn clear(&mut self, tick: u32) {
+        for removed in self.components.values_mut() {
+            removed.retain(|(_, removed_at)| is_newer(*removed_at, tick));
+        }
+        self.despawned.retain(|(_, removed_at)| is_newer(*removed_at, tick));
+    }
+}
+
+fn merge_log(log: &mut Vec<(EntityId, u32)>, other: &[(EntityId, u32)]) {
+    if other.is_empty() {
+        return;
+    }
+    let logged: HashSet<(EntityId, u32)> = log.iter().copied().collect();
+    let missing: Vec<(EntityId, u32)> = other.iter()
+        .filter(|removal| !logged.contains(removal))
+        .copied()
+        .collect();
+    log.splice(0..0, missing);
+}
@@ -0,0 +1,103 @@
+use std::any::{Any, TypeId};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+
+/// Clones a resource stored as an `UnsafeCell<R>`
+type CloneFn = fn(&dyn Any) -> Box<dyn Any>;
+
+fn clone_resource<R: Clone + 'static>(resource: &dyn Any) -> Box<dyn Any> {
+    let cell = resource.downcast_ref::<UnsafeCell<R>>()
+        .unwrap_or_else(|| unreachable!("resources are stored by their type id"));
+    // the resources are borrowed immutably, so no system is accessing them
+    let resource = unsafe { &*cell.get() };
+    return Box::new(UnsafeCell::new(resource.clone()));
+}
+
+/// Stores resources: values of which at most one instance exists per type.
+///
+/// Every resource is stored in its own allocation as an `UnsafeCell<R>`, so that
+/// systems can access resources while the world is borrowed by a query.
+pub(crate) struct Resources {
+    resources: HashMap<TypeId, Box<dyn Any>>,
+    /// The resource types that are cloned with the world
+    clone_fns: HashMap<TypeId, CloneFn>,
+}
+
+impl Resources {
+    pub(crate) fn new() -> Self {
+        Self {
+            resources: HashMap::new(),
+            clone_fns: HashMap::new(),
+        }
+    }
+    
+    /// Marks resources of type `R` to be cloned with the world
+    pub(crate) fn register_clone<R: Clone + 'static>(&mut self) {
+        self.clone_fns.insert(TypeId::of::<R>(), clone_resource::<R>);
+    }
+    
+    /// Clones the resources of which the type is marked to be cloned
+    pub(crate) fn clone_registered(&self) -> Resources {
+        Resources {
+            resources: self.clone_fns.iter()
+                .filter_map(|(type_id, clone)| self.resources.get(type_id).map(|resource| (*type_id, clone(resource.as_ref()))))
+                .collect(),
+            clone_fns: self.clone_fns.clone(),
+        }
+    }
+    
+    /// Replaces the resources of which the type is marked to be cloned by the
+    /// ones in `other`. Resources that don't exist in `other` are removed.
+    pub(crate) fn restore_registered(&mut self, other: &Resources) {
+        for (type_id, clone) in &self.clone_fns {
+            match other.resources.get(type_id) {
+                Some(resource) => self.resources.insert(*type_id, clone(resource.as_ref())),
+                None => self.resources.remove(type_id),
+            };
+        }
+    }
+    
+    /// Inserts a resource, returning the previous resource of this type
+    pub(crate) fn insert<R: 'static>(&mut self, resource: R) -> Option<R> {
+        self.resources.insert(TypeId::of::<R>(), Box::new(UnsafeCell::new(resource)))
+            .map(|old| Self::unbox(old))
+    }
+    
+    pub(crate) fn remove<R: 'static>(&mut self) -> Option<R> {
+        self.resources.remove(&TypeId::of::<R>())
+            .map(|old| Self::unbox(old))
+    }
+    
+    #[inline]
+    pub(crate) fn contains<R: 'static>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+    
+    #[inline]
+    pub(crate) fn get<R: 'static>(&self) -> Option<&R> {
+        self.get_ptr::<R>().map(|ptr| unsafe { &*ptr })
+    }
+    
+    #[inline]
+    pub(crate) fn get_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        self.get_ptr::<R>().map(|ptr| unsafe { &mut *ptr })
+    }
+    
+    /// Pointer to the resource of type `R`
+    #[inline]
+    pub(crate) fn get_ptr<R: 'static>(&self) -> Option<*mut R> {
+        self.resources.get(&TypeId::of::<R>())
+            .map(|resource| {
+                // resources are stored by `TypeId`, so the type is always correct
+                let cell = unsafe { resource.downcast_ref::<UnsafeCell<R>>().unwrap_unchecked() };
+                cell.get()
+            })
+    }
+    
+    fn unbox<R: 'static>(resource: Box<dyn Any>) -> R {
+        match resource.downcast::<UnsafeCell<R>>() {
+            Ok(cell) => cell.into_inner(),
+            Err(_) => unreachable!("resources are stored by their type id"),
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use crate::error::ScheduleError;
+use crate::system::{ParallelRunner, SystemAccess, SystemInfo};
+use crate::world::World;
+
+/// The stages of a [`Schedule`]. All systems of a stage run before the systems of the next stage.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum Stage {
+    PreUpdate,
+    Update,
+    PostUpdate,
+    Render,
+}
+
+impl Stage {
+    /// All stages, in the order they run
+    pub const ALL: [Stage; 4] = [Stage::PreUpdate, Stage::Update, Stage::PostUpdate, Stage::Render];
+}
+
+/// Converts a function into a system that can be added to a [`Schedule`].
+///
+/// Implemented for functions taking `&mut World` or `&World`, such as the
+/// functions created by the `system` macro, and for [`SystemInfo`].
+pub trait IntoSystem<Marker> {
+    fn into_system(self) -> Box<dyn FnMut(&mut World)>;
+    
+    /// The name and access of the system, if known. Only used internally
+    #[doc(hidden)]
+    fn info(&self) -> Option<&SystemInfo> {
+        None
+    }
+}
+
+impl<F: FnMut(&mut World) + 'static> IntoSystem<fn(&mut World)> for F {
+    fn into_system(self) -> Box<dyn FnMut(&mut World)> {
+        Box::new(self)
+    }
+}
+
+impl<F: FnMut(&World) + 'static> IntoSystem<fn(&World)> for F {
+    fn into_system(mut self) -> Box<dyn FnMut(&mut World)> {
+        Box::new(move |world: &mut World| self(world))
+    }
+}
+
+impl IntoSystem<SystemInfo> for SystemInfo {
+    fn into_system(self) -> Box<dyn FnMut(&mut World)> {
+        Box::new(self.system())
+    }
+    
+    fn info(&self) -> Option<&SystemInfo> {
+        Some(self)
+    }
+}
+
+type RunCondition = Box<dyn Fn(&World) -> bool>;
+
+struct SystemEntry {
+    label: &'static str,
+    stage: Stage,
+    system: Box<dyn FnMut(&mut World)>,
+    /// Labels of the systems this system runs before
+    before: Vec<&'static str>,
+    /// Labels of the systems this system runs after
+    after: Vec<&'static str>,
+    /// The system only runs if all conditions return `true`
+    run_conditions: Vec<RunCondition>,
+    /// The access of the system and how to run it on another thread, `None` if
+    /// the system should run on its own
+    parallel: Option<(SystemAccess, ParallelRunner)>,
+    /// The change tick at which the system last ran
+    last_run: u32,
+}
+
+/// Runs systems in stages, ordered by their ordering constraints.
+pub struct Schedule {
+    systems: Vec<SystemEntry>,
+    /// The indices of the systems in the order they run, `None` if the order
+    /// needs to be determined again
+    order: Option<Vec<usize>>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self {
+            systems: Vec::new(),
+            order: None,
+        }
+    }
+    
+    /// Adds a system to a stage. The system's label is the name of the function,
+    /// this can be changed using [`SystemConfig::label`].
+    ///
+    /// Adding the [`SystemInfo`] of a system (`<name>_info()` for systems created
+    /// by the `system` macro) allows it to run in parallel, see [`run_parallel`](Schedule::run_parallel).
+    pub fn add_system<Marker>(&mut self, stage: Stage, system: impl IntoSystem<Marker>) -> SystemConfig<'_> {
+        let (label, parallel) = match system.info() {
+            Some(info) => (info.name(), info.parallel().map(|run| (info.access().clone(), run))),
+            None => (short_type_name(std::any::type_name_of_val(&system)), None)
+        };
+        self.systems.push(SystemEntry {
+            label,
+            stage,
+            system: system.into_system(),
+            before: Vec::new(),
+            after: Vec::new(),
+            run_conditions: Vec::new(),
+            parallel,
+            last_run: 0,
+        });
+        self.order = None;
+        return SystemConfig { entry: self.systems.last_mut().unwrap() };
+    }
+    
+    /// Determines the order of the systems. This is done automatically by
+    /// [`run`](Schedule::run), but can be used to check the ordering
+    /// constraints up front.
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        let mut order = Vec::with_capacity(self.systems.len());
+        for stage in Stage::ALL {
+            order.append(&mut self.stage_order(stage)?);
+        }
+        self.order = Some(order);
+        return Ok(());
+    }
+    
+    /// Runs all systems whose run conditions are met. The `Added` and `Changed`
+    /// filters of a system report the changes made since the system last ran.
+    ///
+    /// # Panics
+    /// If the order of the systems can't be determined, see [`build`](Schedule::build)
+    pub fn run(&mut self, world: &mut World) {
+        if self.order.is_none() {
+            self.build().unwrap_or_else(|err| panic!("{}", err));
+        }
+        
+        let order = self.order.as_ref().unwrap();
+        for i in order {
+            let entry = &mut self.systems[*i];
+            if entry.run_conditions.iter().all(|condition| condition(world)) {
+                world.run_tracked(&mut entry.last_run, &mut entry.system);
+            }
+        }
+        self.forget_removals(world);
+        world.update_events();
+    }
+    
+    /// Runs all systems whose run conditions are met, running systems whose
+    /// accesses don't conflict at the same time on multiple threads.
+    ///
+    /// The systems are split into batches, following the order of [`run`](Schedule::run).
+    /// A system joins the current batch if its access doesn't conflict with the
+    /// systems in the batch, it has no ordering constraints with them and it is
+    /// in the same stage. Only systems added as a [`SystemInfo`] can share a batch
+    /// with other systems. The run conditions of a batch are checked before the
+    /// batch runs.
+    ///
+    /// # Panics
+    /// If the order of the systems can't be determined, see [`build`](Schedule::build),
+    /// or if a system panics
+    pub fn run_parallel(&mut self, world: &mut World) {
+        if self.order.is_none() {
+            self.build().unwrap_or_else(|err| panic!("{}", err));
+        }
+        
+        let order = self.order.take().unwrap();
+        let mut batch: Vec<usize> = Vec::new();
+        for i in order.iter().copied() {
+            if !batch.iter().all(|j| self.can_run_together(i, *j)) {
+                self.run_batch(&batch, world);
+                batch.clear();
+            }
+            batch.push(i);
+        }
+        self.run_batch(&batch, world);
+        self.order = Some(order);
+        self.forget_removals(world);
+        world.update_events();
+    }
+    
+    /// Forgets the removed components and killed entities all systems that ran
+    /// have seen, which are the removals made at or before the oldest `last_run`
+    fn forget_removals(&self, world: &mut World) {
+        let change_tick = world.change_tick();
+        // ticks wrap around, so the oldest tick is the one furthest behind the current tick
+        let oldest = self.systems.iter()
+            .map(|entry| entry.last_run)
+            // systems that never ran, e.g. because of their run conditions, haven't
+            // seen anything, but shouldn't keep the removals around forever
+            .filter(|last_run| *last_run != 0)
+            .max_by_key(|last_run| change_tick.wrapping_sub(*last_run));
+        if let Some(seen) = oldest {
+            world.forget_removals(seen);
+        }
+    }
+    
+    /// Whether the systems at indices `a` and `b` can run at the same time
+    fn can_run_together(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.systems[a], &self.systems[b]);
+        match (&a.parallel, &b.parallel) {
+            (Some((access_a, _)), Some((access_b, _))) => {
+                a.stage == b.stage
+                    && !access_a.conflicts_with(access_b)
+                    && !a.before.contains(&b.label) && !a.after.contains(&b.label)
+                    && !b.before.contains(&a.label) && !b.after.contains(&a.label)
+            }
+            _ => false
+        }
+    }
+    
+    /// Runs a batch of systems created by [`run_parallel`](Schedule::run_parallel)
+    fn run_batch(&mut self, batch: &[usize], world: &mut World) {
+        let batch: Vec<usize> = batch.iter()
+            .copied()
+            .filter(|i| self.systems[*i].run_conditions.iter().all(|condition| condition(world)))
+            .collect();
+        
+        match batch.as_slice() {
+            [] => {},
+            [i] => {
+                let entry = &mut self.systems[*i];
+                world.run_tracked(&mut entry.last_run, &mut entry.system);
+            }
+            [first, rest @ ..] => {
+                let runner = |i: usize| (self.systems[i].parallel.as_ref().unwrap().1, self.systems[i].last_run);
+                let shared = SharedWorld(world);
+                std::thread::scope(|scope| {
+                    for i in rest {
+                        let (run, last_run) = runner(*i);
+                        // Safety: the systems in a batch don't conflict and only access
+                        // components and resources that are `Send` and `Sync`
+                        scope.spawn(move || unsafe { run(shared.get(), last_run) });
+                    }
+                    let (run, last_run) = runner(*first);
+                    unsafe { run(shared.get(), last_run) };
+                });
+                
+                // all systems in the batch ran at the same tick
+                for i in &batch {
+                    self.systems[*i].last_run = world.change_tick();
+                }
+                world.increment_change_tick();
+            }
+        }
+    }
+    
+    /// Sorts the systems of `stage` so that all ordering constraints are met.
+    /// Systems without constraints between them keep the order they were added in.
+    fn stage_order(&self, stage: Stage) -> Result<Vec<usize>, ScheduleError> {
+        let systems: Vec<usize> = (0..self.systems.len())
+            .filter(|i| self.systems[*i].stage == stage)
+            .collect();
+        
+        // edges[a] contains b if a runs before b (indices into `systems`)
+        let mut edges: Vec<Vec<usize>> = vec![Vec::new(); systems.len()];
+        for (a, i) in systems.iter().enumerate() {
+            let entry = &self.systems[*i];
+            for label in &entry.before {
+                for b in self.find_label(&systems, entry.label, label)? {
+                    edges[a].push(b);
+                }
+            }
+            for label in &entry.after {
+                for b in self.find_label(&systems, entry.label, label)? {
+                    edges[b].push(a);
+                }
+            }
+        }
+        
+        let mut in_degree = vec![0; systems.len()];
+        edges.iter().flatten().for_each(|b| in_degree[*b] += 1);
+        
+        let mut order = Vec::with_capacity(systems.len());
+        let mut done = vec![false; systems.len()];
+        while order.len() < systems.len() {
+            // the first system that has no systems left that should run before it
+            let Some(next) = (0..systems.len()).find(|a| !done[*a] && in_degree[*a] == 0) else {
+                let cycle = find_cycle(&edges, &done);
+                return Err(ScheduleError::Cycle {
+                    stage,
+                    systems: cycle.iter().map(|a| self.systems[systems[*a]].label).collect()
+                });
+            };
+            done[next] = true;
+            edges[next].iter().for_each(|b| in_degree[*b] -= 1);
+            order.push(systems[next]);
+        }
+        
+        return Ok(order);
+    }
+    
+    /// The indices into `systems` of the systems with the given label. Labels
+    /// of systems in other stages are ordered by their stage.
+    fn find_label(&self, systems: &[usize], system: &'static str, label: &'static str) -> Result<Vec<usize>, ScheduleError> {
+        if !self.systems.iter().any(|entry| entry.label == label) {
+            return Err(ScheduleError::UnknownLabel { system, label });
+        }
+        return Ok(systems.iter()
+            .enumerate()
+            .filter(|(_, i)| self.systems[**i].label == label)
+            .map(|(a, _)| a)
+            .collect());
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A world that is shared with the threads running a batch of systems
+#[derive(Clone, Copy)]
+struct SharedWorld<'a>(&'a World);
+
+// Safety: systems in the same batch only access components and resources that
+// are `Send` and `Sync` and don't conflict, the rest of the world is only read
+unsafe impl Send for SharedWorld<'_> {}
+
+impl<'a> SharedWorld<'a> {
+    /// Used instead of accessing the field, so that closures capture the whole `SharedWorld`
+    #[inline]
+    fn get(self) -> &'a World {
+        self.0
+    }
+}
+
+/// Configures a system that was added to a [`Schedule`]
+pub struct SystemConfig<'a> {
+    entry: &'a mut SystemEntry,
+}
+
+impl<'a> SystemConfig<'a> {
+    /// Sets the label other systems use to order themselves relative to this system
+    pub fn label(self, label: &'static str) -> Self {
+        self.entry.label = label;
+        self
+    }
+    
+    /// Runs this system before the systems with the given label
+    pub fn before(self, label: &'static str) -> Self {
+        self.entry.before.push(label);
+        self
+    }
+    
+    /// Runs this system after the systems with the given label
+    pub fn after(self, label: &'static str) -> Self {
+        self.entry.after.push(label);
+        self
+    }
+    
+    /// Only runs this system if `condition` returns `true`
+    pub fn run_if(self, condition: impl Fn(&World) -> bool + 'static) -> Self {
+        self.entry.run_conditions.push(Box::new(condition));
+        self
+    }
+}
+
+/// Finds a cycle among the nodes that are not done, returning the nodes in the cycle
+fn find_cycle(edges: &[Vec<usize>], done: &[bool]) -> Vec<usize> {
+    // every node that is not done has an incoming edge from another node that
+    // is not done, so walking backwards eventually visits a node twice
+    let mut path: Vec<usize> = Vec::new();
+    let mut node = (0..done.len()).find(|a| !done[*a]).unwrap();
+    loop {
+        if let Some(pos) = path.iter().position(|a| *a == node) {
+            let mut cycle = path.split_off(pos);
+            cycle.reverse();
+            // start at the system that was added first
+            let first = (0..cycle.len()).min_by_key(|i| cycle[*i]).unwrap();
+            cycle.rotate_left(first);
+            return cycle;
+        }
+        path.push(node);
+        node = (0..edges.len())
+            .find(|a| !done[*a] && edges[*a].contains(&node))
+            .unwrap();
+    }
+}
+
+/// The last path segment of a type name, e.g. `movement` for `my_game::systems::movement`
+fn short_type_name(name: &'static str) -> &'static str {
+    // don't split inside generics
+    let end = name.find('<').unwrap_or(name.len());
+    match name[..end].rfind("::") {
+        Some(pos) => &name[pos + 2..],
+        None => name
+    }
+}
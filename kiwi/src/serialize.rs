@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::arch::{Archetype, ArchRowId, ComponentInfo};
+use crate::component::{Component, ComponentId, component_name};
+use crate::entity::EntityId;
+use crate::error::SerializeError;
+
+/// A value that can be saved to and loaded from bytes, used to save the world.
+///
+/// Components implement this trait using `#[component(serialize)]`, other types
+/// using `#[derive(Serializable)]`. Values are stored in little-endian order.
+pub trait Serializable: Sized {
+    /// Appends the value to `out`
+    fn serialize(&self, out: &mut Vec<u8>);
+    
+    /// Reads a value from the start of `input`, advancing it past the value
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError>;
+}
+
+/// Takes the first `len` bytes of `input`
+#[inline]
+pub(crate) fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], SerializeError> {
+    if input.len() < len {
+        return Err(SerializeError::UnexpectedEnd);
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    return Ok(bytes);
+}
+
+/// Reads a length, which is stored as a `u32`
+#[inline]
+fn read_len(input: &mut &[u8]) -> Result<usize, SerializeError> {
+    return Ok(u32::deserialize(input)? as usize);
+}
+
+/// Writes a length as a `u32`
+#[inline]
+fn write_len(len: usize, out: &mut Vec<u8>) {
+    let len: u32 = len.try_into().expect("can't serialize more than u32::MAX items");
+    len.serialize(out);
+}
+
+macro_rules! impl_serializable_number {
+    ($($ty:ty),*) => {
+        $(
+            impl Serializable for $ty {
+                #[inline]
+                fn serialize(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+                
+                #[inline]
+                fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+                    let bytes = take(input, std::mem::size_of::<$ty>())?;
+                    return Ok(<$ty>::from_le_bytes(bytes.try_into().unwrap()));
+                }
+            }
+        )*
+    };
+}
+
+impl_serializable_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+// sizes are stored as 64 bit values, so they can be loaded on other platforms
+impl Serializable for usize {
+    #[inline]
+    fn serialize(&self, out: &mut Vec<u8>) {
+        (*self as u64).serialize(out);
+    }
+    
+    #[inline]
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+        return usize::try_from(u64::deserialize(input)?).map_err(|_| SerializeError::InvalidValue("usize"));
+    }
+}
+
+impl Serializable for isize {
+    #[inline]
+    fn serialize(&self, out: &mut Vec<u8>) {
+        (*self as i64).serialize(out);
+    }
+    
+    #[inline]
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+        return isize::try_from(i64::deserialize(input)?).map_err(|_| SerializeError::InvalidValue("isize"));
+    }
+}
+
+impl Serializable for bool {
+    #[inline]
+    fn serialize(&self, out: &mut Vec<u8>) {
+        (*self as u8).serialize(out);
+    }
+    
+    #[inline]
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+        return match u8::deserialize(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SerializeError::InvalidValue("bool")),
+        };
+    }
+}
+
+impl Serializable for char {
+    #[inline]
+    fn serialize(&self, out: &mut Vec<u8>) {
+        (*self as u32).serialize(out);
+    }
+    
+    #[inline]
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+        return char::from_u32(u32::deserialize(input)?).ok_or(SerializeError::InvalidValue("char"));
+    }
+}
+
+impl Serializable for String {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        write_len(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+    
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+        let len = read_len(input)?;
+        let bytes = take(input, len)?;
+        return String::from_utf8(bytes.to_vec()).map_err(|_| SerializeError::InvalidValue("String"));
+    }
+}
+
+impl<T: Serializable> Serializable for Vec<T> {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        write_len(self.len(), out);
+        for item in self {
+            item.serialize(out);
+        }
+    }
+    
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+        let len = read_len(input)?;
+        // the length is not trusted, every item takes at least one byte
+        let mut items = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            items.push(T::deserialize(input)?);
+        }
+        return Ok(items);
+    }
+}
+
+impl<T: Serializable> Serializable for Option<T> {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        match self {
+            Some(value) => {
+                1u8.serialize(out);
+                value.serialize(out);
+            },
+            None => 0u8.serialize(out),
+        }
+    }
+    
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+        return match u8::deserialize(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::deserialize(input)?)),
+            _ => Err(SerializeError::InvalidValue("Option")),
+        };
+    }
+}
+
+impl<T: Serializable, const N: usize> Serializable for [T; N] {
+    fn serialize(&self, out: &mut Vec<u8>) {
+        for item in self {
+            item.serialize(out);
+        }
+    }
+    
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+        let items = (0..N).map(|_| T::deserialize(input)).collect::<Result<Vec<T>, SerializeError>>()?;
+        return match items.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("exactly N items are deserialized"),
+        };
+    }
+}
+
+macro_rules! impl_serializable_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: Serializable),*> Serializable for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn serialize(&self, out: &mut Vec<u8>) {
+                let ($($name,)*) = self;
+                $($name.serialize(out);)*
+            }
+            
+            fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+                return Ok(($($name::deserialize(input)?,)*));
+            }
+        }
+    };
+}
+
+impl_serializable_tuple!(A);
+impl_serializable_tuple!(A, B);
+impl_serializable_tuple!(A, B, C);
+impl_serializable_tuple!(A, B, C, D);
+
+impl Serializable for EntityId {
+    #[inline]
+    fn serialize(&self, out: &mut Vec<u8>) {
+        self.index().serialize(out);
+        self.generation().serialize(out);
+    }
+    
+    #[inline]
+    fn deserialize(input: &mut &[u8]) -> Result<Self, SerializeError> {
+        return Ok(EntityId::new(u32::deserialize(input)?, u32::deserialize(input)?));
+    }
+}
+
+//=====================
+// Component registry
+//=====================
+
+/// Type-erased functions to save and load a component
+#[derive(Clone, Copy)]
+pub(crate) struct ComponentSerializer {
+    /// The name the component is saved under
+    pub(crate) name: &'static str,
+    pub(crate) info: ComponentInfo,
+    /// Serializes the component in a row of an archetype
+    pub(crate) serialize: unsafe fn(&Archetype, ArchRowId, &mut Vec<u8>),
+    /// Deserializes the component into a row of an archetype that doesn't
+    /// contain a component of this type yet, adding it at the given tick
+    pub(crate) deserialize: fn(&mut &[u8], &mut Archetype, ArchRowId, u32) -> Result<(), SerializeError>,
+}
+
+/// The serializers of the components, registered the first time their id is requested
+static SERIALIZERS: RwLock<BTreeMap<ComponentId, ComponentSerializer>> = RwLock::new(BTreeMap::new());
+
+/// # Safety
+/// The row should contain an initialised component of type `T`
+unsafe fn serialize_component<T: Component + Serializable + 'static>(archetype: &Archetype, row: ArchRowId, out: &mut Vec<u8>) {
+    archetype.get_component::<T>(row).serialize(out);
+}
+
+fn deserialize_component<T: Component + Serializable + 'static>(input: &mut &[u8], archetype: &mut Archetype, row: ArchRowId, tick: u32) -> Result<(), SerializeError> {
+    let component = T::deserialize(input)?;
+    archetype.init_component(row, component, tick);
+    return Ok(());
+}
+
+/// Registers component `T` with id `id` to be saved under `name`. Only used
+/// internally, by `#[component(serialize)]` when the id of the component is assigned
+///
+/// # Panics
+/// If another component is saved under the same name
+#[doc(hidden)]
+pub fn register_serializable<T: Component + Serializable + 'static>(id: ComponentId, name: &'static str) {
+    let mut serializers = SERIALIZERS.write().unwrap();
+    if let Some((other, _)) = serializers.iter().find(|(other, serializer)| **other != id && serializer.name == name) {
+        panic!(
+            "Components {} and {} are both saved as `{}`",
+            std::any::type_name::<T>(), component_name(*other).unwrap_or("?"), name
+        );
+    }
+    serializers.entry(id).or_insert(ComponentSerializer {
+        name,
+        info: ComponentInfo::of::<T>(),
+        serialize: serialize_component::<T>,
+        deserialize: deserialize_component::<T>,
+    });
+}
+
+/// The serializer of a component, `None` if the component is not serializable
+pub(crate) fn serializer(id: ComponentId) -> Option<ComponentSerializer> {
+    SERIALIZERS.read().unwrap().get(&id).copied()
+}
+
+/// The id and serializer of the component saved under `name`
+pub(crate) fn serializer_by_name(name: &str) -> Option<(ComponentId, ComponentSerializer)> {
+    SERIALIZERS.read().unwrap().iter()
+        .find(|(_, serializer)| serializer.name == name)
+        .map(|(id, serializer)| (*id, *serializer))
+}
+
+//=====================
+// Saved worlds
+//=====================
+
+/// The first bytes of a saved world
+pub(crate) const SAVE_MAGIC: &[u8; 4] = b"KIWI";
+/// The version of the format of saved worlds
+pub(crate) const SAVE_VERSION: u32 = 1;
+
+/// Appends the given components of a row, each prefixed with its size in bytes
+///
+/// # Safety
+/// The row should contain initialised components with the given ids
+pub(crate) unsafe fn serialize_row(archetype: &Archetype, row: ArchRowId, components: &[(ComponentId, ComponentSerializer)], out: &mut Vec<u8>) {
+    for (_, serializer) in components {
+        let start = out.len();
+        out.extend_from_slice(&[0; 4]);
+        (serializer.serialize)(archetype, row, out);
+        let len: u32 = (out.len() - start - 4).try_into().expect("can't serialize components larger than u32::MAX bytes");
+        out[start..start + 4].copy_from_slice(&len.to_le_bytes());
+    }
+}
+
+/// Reads the components written by `serialize_row` into a new row. If a component
+/// can't be read, the components that were read are dropped and the row is freed.
+pub(crate) fn deserialize_row(input: &mut &[u8], archetype: &mut Archetype, row: ArchRowId, components: &[(ComponentId, ComponentSerializer)], tick: u32) -> Result<(), SerializeError> {
+    for (i, (_, serializer)) in components.iter().enumerate() {
+        let result = read_len(input)
+            .and_then(|len| take(input, len))
+            .and_then(|mut bytes| {
+                (serializer.deserialize)(&mut bytes, archetype, row, tick)?;
+                if bytes.is_empty() {
+                    return Ok(());
+                }
+                // the component was read, but didn't use all of its bytes
+                unsafe { archetype.drop_component(components[i].0, row); }
+                return Err(SerializeError::InvalidFormat);
+            });
+        if let Err(err) = result {
+            for (id, _) in &components[..i] {
+                unsafe { archetype.drop_component(*id, row); }
+            }
+            // the row is the last row, so no entity is moved into it
+            archetype.remove_moved_entity(row);
+            return Err(err);
+        }
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn round_trip<T: Serializable + PartialEq + std::fmt::Debug>(value: T) {
+        let mut bytes = Vec::new();
+        value.serialize(&mut bytes);
+        let mut input = bytes.as_slice();
+        assert_eq!(T::deserialize(&mut input).unwrap(), value);
+        assert!(input.is_empty());
+    }
+    
+    #[test]
+    fn serializable_values() {
+        round_trip(-5i32);
+        round_trip(1.5f64);
+        round_trip(usize::MAX);
+        round_trip(true);
+        round_trip('k');
+        round_trip(String::from("kiwi"));
+        round_trip(vec![Some(1u8), None]);
+        round_trip([(1u16, -1i64); 3]);
+        round_trip(EntityId::new(3, 1));
+        
+        assert!(matches!(bool::deserialize(&mut [2u8].as_slice()), Err(SerializeError::InvalidValue("bool"))));
+        assert!(matches!(u32::deserialize(&mut [0u8; 3].as_slice()), Err(SerializeError::UnexpectedEnd)));
+        // the length of the vector is larger than the input
+        assert!(matches!(Vec::<u8>::deserialize(&mut [255u8; 4].as_slice()), Err(SerializeError::UnexpectedEnd)));
+    }
+}
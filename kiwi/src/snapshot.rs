@@ -0,0 +1,35 @@
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+use crate::arch::{CloneFn, clone_component};
+use crate::component::{Component, ComponentId, component_name};
+use crate::world::World;
+
+/// The clone functions of the components, registered the first time their id is requested
+static CLONE_FNS: RwLock<BTreeMap<ComponentId, CloneFn>> = RwLock::new(BTreeMap::new());
+
+/// Registers component `T` with id `id` to be cloned with the world. Only used
+/// internally, by `#[component(clone)]` when the id of the component is assigned
+#[doc(hidden)]
+pub fn register_clone<T: Component + Clone + 'static>(id: ComponentId) {
+    CLONE_FNS.write().unwrap().entry(id).or_insert(clone_component::<T>);
+}
+
+/// The function cloning a component, `None` if the component is not cloneable
+pub(crate) fn clone_fn(id: ComponentId) -> Option<CloneFn> {
+    CLONE_FNS.read().unwrap().get(&id).copied()
+}
+
+/// Panics with a message telling the user to make the component cloneable
+pub(crate) fn not_cloneable(id: ComponentId) -> ! {
+    panic!(
+        "Component {} can't be cloned, add `#[component(clone)]` to it",
+        component_name(id).unwrap_or("?")
+    );
+}
+
+/// A copy of the state of a world, created by [`World::snapshot`] and
+/// restored by [`World::restore`]
+pub struct WorldSnapshot {
+    pub(crate) world: World,
+}
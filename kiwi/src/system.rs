@@ -0,0 +1,244 @@
+use std::any::TypeId;
+use std::marker::PhantomData;
+
+use crate::component::{Component, ComponentId};
+use crate::world::World;
+
+/// The components and resources a system reads and writes.
+///
+/// Systems whose accesses don't conflict can run at the same time, see
+/// [`Schedule::run_parallel`](crate::Schedule::run_parallel).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemAccess {
+    reads: Vec<ComponentId>,
+    writes: Vec<ComponentId>,
+    resource_reads: Vec<TypeId>,
+    resource_writes: Vec<TypeId>,
+    exclusive: bool,
+}
+
+impl SystemAccess {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// The system reads component `T`
+    pub fn read<T: Component + 'static>(mut self) -> Self {
+        if !self.reads.contains(&T::id()) {
+            self.reads.push(T::id());
+        }
+        self
+    }
+    
+    /// The system writes to component `T`
+    pub fn write<T: Component + 'static>(mut self) -> Self {
+        if !self.writes.contains(&T::id()) {
+            self.writes.push(T::id());
+        }
+        self
+    }
+    
+    /// The system reads resource `R`
+    pub fn read_resource<R: 'static>(mut self) -> Self {
+        if !self.resource_reads.contains(&TypeId::of::<R>()) {
+            self.resource_reads.push(TypeId::of::<R>());
+        }
+        self
+    }
+    
+    /// The system writes to resource `R`
+    pub fn write_resource<R: 'static>(mut self) -> Self {
+        if !self.resource_writes.contains(&TypeId::of::<R>()) {
+            self.resource_writes.push(TypeId::of::<R>());
+        }
+        self
+    }
+    
+    /// The system accesses components or resources that aren't `Send` and
+    /// `Sync`, so it conflicts with every other system and runs on its own
+    pub fn exclusive(mut self) -> Self {
+        self.exclusive = true;
+        self
+    }
+    
+    /// The components the system reads
+    #[inline]
+    pub fn reads(&self) -> &[ComponentId] {
+        &self.reads
+    }
+    
+    /// The components the system writes to
+    #[inline]
+    pub fn writes(&self) -> &[ComponentId] {
+        &self.writes
+    }
+    
+    /// The resources the system reads
+    #[inline]
+    pub fn resource_reads(&self) -> &[TypeId] {
+        &self.resource_reads
+    }
+    
+    /// The resources the system writes to
+    #[inline]
+    pub fn resource_writes(&self) -> &[TypeId] {
+        &self.resource_writes
+    }
+    
+    /// Whether the system can't run at the same time as any other system
+    #[inline]
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+    
+    /// Whether one of the systems is exclusive, or writes to a component or
+    /// resource the other system reads or writes to
+    pub fn conflicts_with(&self, other: &SystemAccess) -> bool {
+        if self.exclusive || other.exclusive {
+            return true;
+        }
+        let writes_to = |writes: &[ComponentId], access: &SystemAccess| {
+            writes.iter().any(|id| access.reads.contains(id) || access.writes.contains(id))
+        };
+        let writes_to_resource = |writes: &[TypeId], access: &SystemAccess| {
+            writes.iter().any(|id| access.resource_reads.contains(id) || access.resource_writes.contains(id))
+        };
+        return writes_to(&self.writes, other)
+            || writes_to(&other.writes, self)
+            || writes_to_resource(&self.resource_writes, other)
+            || writes_to_resource(&other.resource_writes, self);
+    }
+}
+
+/// Runs a system using a shared reference to the world. Changes made after the
+/// given tick are reported by the `Added` and `Changed` filters of the system.
+pub(crate) type ParallelRunner = unsafe fn(&World, u32);
+
+/// A system together with the components and resources it accesses.
+///
+/// The `system` macro creates a function `<name>_info` returning the info of
+/// the system, which can be added to a [`Schedule`](crate::Schedule) instead of
+/// the system itself.
+pub struct SystemInfo {
+    name: &'static str,
+    access: SystemAccess,
+    system: fn(&mut World),
+    parallel: Option<ParallelRunner>,
+}
+
+impl SystemInfo {
+    /// Created by the `system` macro
+    ///
+    /// # Safety
+    /// `parallel` should only access the components and resources in `access`,
+    /// which should be `Send` and `Sync`
+    #[doc(hidden)]
+    pub unsafe fn new(name: &'static str, access: SystemAccess, system: fn(&mut World), parallel: Option<ParallelRunner>) -> Self {
+        Self { name, access, system, parallel }
+    }
+    
+    /// The name of the system's function
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    
+    #[inline]
+    pub fn access(&self) -> &SystemAccess {
+        &self.access
+    }
+    
+    /// Whether the system can run at the same time as other systems. Systems
+    /// with an immutable world or a command buffer, and systems using components
+    /// or resources that aren't `Send` and `Sync`, always run on their own.
+    #[inline]
+    pub fn is_parallel(&self) -> bool {
+        self.parallel.is_some()
+    }
+    
+    #[inline]
+    pub(crate) fn system(&self) -> fn(&mut World) {
+        self.system
+    }
+    
+    #[inline]
+    pub(crate) fn parallel(&self) -> Option<ParallelRunner> {
+        self.parallel
+    }
+}
+
+/// Checks whether `T` is `Send` and `Sync` in code generated by the `system`
+/// macro, by calling `(&SendSyncCheck::<T>(PhantomData)).is_send_sync()` with
+/// [`IsSendSync`] and [`NotSendSync`] in scope. Only used internally
+#[doc(hidden)]
+pub struct SendSyncCheck<T>(pub PhantomData<T>);
+
+#[doc(hidden)]
+pub trait IsSendSync {
+    #[inline]
+    fn is_send_sync(&self) -> bool {
+        true
+    }
+}
+
+impl<T: Send + Sync> IsSendSync for SendSyncCheck<T> {}
+
+/// Only found by method resolution if [`IsSendSync`] isn't implemented, as it
+/// takes an extra reference
+#[doc(hidden)]
+pub trait NotSendSync {
+    #[inline]
+    fn is_send_sync(&self) -> bool {
+        false
+    }
+}
+
+impl<T> NotSendSync for &SendSyncCheck<T> {}
+
+#[cfg(test)]
+mod tests {
+    use std::marker::PhantomData;
+    use std::rc::Rc;
+    
+    use crate::*;
+    use crate as kiwi_ecs;
+    use super::{SendSyncCheck, IsSendSync, NotSendSync};
+    
+    #[derive(Component)]
+    struct A {}
+    #[derive(Component)]
+    struct B {}
+    
+    #[test]
+    fn access_conflicts() {
+        let read_a = SystemAccess::new().read::<A>();
+        let write_a = SystemAccess::new().write::<A>();
+        let write_b = SystemAccess::new().read::<A>().write::<B>();
+        
+        assert!(!read_a.conflicts_with(&read_a));
+        assert!(read_a.conflicts_with(&write_a));
+        assert!(write_a.conflicts_with(&read_a));
+        assert!(write_a.conflicts_with(&write_a));
+        assert!(!read_a.conflicts_with(&write_b));
+        assert!(write_a.conflicts_with(&write_b));
+        
+        let read_res = SystemAccess::new().read_resource::<u32>();
+        let write_res = SystemAccess::new().write_resource::<u32>();
+        assert!(!read_res.conflicts_with(&read_res));
+        assert!(read_res.conflicts_with(&write_res));
+        assert!(!write_res.conflicts_with(&write_a));
+        
+        let exclusive = SystemAccess::new().exclusive();
+        assert!(exclusive.conflicts_with(&SystemAccess::new()));
+        assert!(read_a.conflicts_with(&exclusive));
+    }
+    
+    #[test]
+    // the borrow selects the implementation
+    #[allow(clippy::needless_borrow)]
+    fn send_sync_check() {
+        assert!((&SendSyncCheck::<u32>(PhantomData)).is_send_sync());
+        assert!(!(&SendSyncCheck::<Rc<u32>>(PhantomData)).is_send_sync());
+        assert!(!(&SendSyncCheck::<std::cell::Cell<u32>>(PhantomData)).is_send_sync());
+    }
+}
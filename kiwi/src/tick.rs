@@ -0,0 +1,26 @@
+/// Whether `tick` is more recent than `last_change_tick`.
+///
+/// Change ticks wrap around when they overflow, so ticks are compared by their
+/// distance instead of by their value: a tick is newer if it lies less than
+/// half of the range of `u32` after `last_change_tick`. Changes are reported
+/// correctly as long as a system runs at least once every `u32::MAX / 2` ticks.
+#[inline]
+pub(crate) fn is_newer(tick: u32, last_change_tick: u32) -> bool {
+    let age = tick.wrapping_sub(last_change_tick);
+    return age != 0 && age < u32::MAX / 2;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_newer;
+    
+    #[test]
+    fn wrapping_ticks() {
+        assert!(is_newer(2, 1));
+        assert!(!is_newer(1, 1));
+        assert!(!is_newer(1, 2));
+        assert!(is_newer(0, u32::MAX));
+        assert!(is_newer(3, u32::MAX - 3));
+        assert!(!is_newer(u32::MAX, 0));
+    }
+}
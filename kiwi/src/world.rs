@@ -1,11 +1,33 @@
-use crate::entity::{EntityStore, EntityId};
-use crate::arch::{ArchStore, NewEntityResult};
-use crate::component::{Component, Flag};
+use crate::entity::{EntityStore, EntityId, IdReserver};
+use crate::arch::{ArchStore, NewEntityResult, ComponentInfo, ArchetypeId, ArchRowId};
+use std::any::TypeId;
+use std::sync::Arc;
+
+use crate::commands::Commands;
+use crate::component::{Component, ComponentId, Flag, FlagId};
+use crate::error::{KiwiError, SerializeError};
+use crate::event::{Events, EventReader, EventUpdater};
+use crate::query::{ChunkQuery, ReadOnlyChunkQuery};
+use crate::removed::Removals;
+use crate::resource::Resources;
+use crate::serialize::{self, Serializable, ComponentSerializer, SAVE_MAGIC, SAVE_VERSION};
+use crate::snapshot::{self, WorldSnapshot};
 
 /// The `World` is the entry point to an ecs
 pub struct World {
     entity_store: EntityStore,
     arch_store: ArchStore,
+    resources: Resources,
+    removals: Removals,
+    /// Updates the event queue of every event type added to the world
+    event_updaters: Vec<(TypeId, EventUpdater)>,
+    /// The change tick at which the event queues were last updated, so that a
+    /// frame run by a schedule and ended by `clear_trackers` updates them once
+    events_updated_at: Option<u32>,
+    /// The tick at which components are added and changed
+    change_tick: u32,
+    /// Changes made after this tick are reported by `Added` and `Changed` filters
+    last_change_tick: u32,
 }
 
 impl World {
@@ -13,48 +35,116 @@ impl World {
         Self {
             entity_store: EntityStore::new(),
             arch_store: ArchStore::new(),
+            resources: Resources::new(),
+            removals: Removals::new(),
+            event_updaters: Vec::new(),
+            events_updated_at: None,
+            change_tick: 1,
+            last_change_tick: 0,
         }
     }
 
     kiwi_internal_macros::gen_spawn_entity!();
+    
+    /// Adds a component to an existing entity.
+    ///
+    /// The entity is moved to the archetype matching its new set of components.
+    /// If the entity already has a component of type `T`, it is overwritten.
+    ///
+    /// Returns `false` if the entity is not alive.
+    pub fn insert_component<T: Component + 'static>(&mut self, entity: EntityId, comp: T) -> bool {
+        let Some(ent) = self.entity_store.get(entity) else {
+            return false;
+        };
+        let archetype = self.arch_store.get_archetype_mut(ent.arch_id);
+        if archetype.has_component(T::id()) {
+            archetype.set_component(ent.arch_row, comp, self.change_tick);
+            return true;
+        }
+        
+        let mut components = archetype.component_ids();
+        components.push(T::id());
+        components.sort();
+        
+        let (old_arch_id, old_arch_row) = (ent.arch_id, ent.arch_row);
+        let (arch_id, arch_row, moved) = self.arch_store.move_entity(
+            entity,
+            ent,
+            &components,
+            Some((T::id(), ComponentInfo::of::<T>()))
+        );
+        self.arch_store.get_archetype_mut(arch_id).init_component(arch_row, comp, self.change_tick);
+        self.entity_store.move_entity(entity, arch_id, arch_row);
+        if let Some(moved) = moved {
+            self.entity_store.move_entity(moved, old_arch_id, old_arch_row);
+        }
+        return true;
+    }
+    
+    /// Removes a component from an entity and returns it.
+    ///
+    /// The entity is moved to the archetype matching its new set of components.
+    /// Returns `None` if the entity is not alive or doesn't have a component of type `T`.
+    pub fn remove_component<T: Component + 'static>(&mut self, entity: EntityId) -> Option<T> {
+        let ent = self.entity_store.get(entity)?;
+        let archetype = self.arch_store.get_archetype_mut(ent.arch_id);
+        if !archetype.has_component(T::id()) {
+            return None;
+        }
+        
+        let comp = unsafe { archetype.take_component::<T>(ent.arch_row) };
+        let mut components = archetype.component_ids();
+        components.retain(|id| *id != T::id());
+        
+        let (old_arch_id, old_arch_row) = (ent.arch_id, ent.arch_row);
+        let (arch_id, arch_row, moved) = self.arch_store.move_entity(entity, ent, &components, None);
+        self.entity_store.move_entity(entity, arch_id, arch_row);
+        if let Some(moved) = moved {
+            self.entity_store.move_entity(moved, old_arch_id, old_arch_row);
+        }
+        self.removals.component_removed(T::id(), entity, self.change_tick);
+        
+        Some(comp)
+    }
 
-    /// Kills an entity
+    /// Kills an entity, dropping its components
+    ///
+    /// The index of the `EntityId` will be reused for other entities, but with
+    /// a new generation, so `world.is_alive(the_killed_entity_id)` stays accurate.
+    /// Killing an entity that is not alive does nothing.
     ///
-    /// This means the `EntityId` will be reused for other entities. This also
-    /// implies that `world.is_alive(the_killed_enity_id)` will not be accurate.
-    /// If you want to keep an id killed, use `world.kill_and_keep(id)`. You should
-    /// still free those ids using `world.free_id` later so that the memory can be reused.
+    /// The entity is reported by [`despawned`](World::despawned) and by
+    /// [`removed`](World::removed) for each of its components.
     pub fn kill(&mut self, ent_id: EntityId) {
-        let ent = &self.entity_store.entities()[ent_id as usize];
-        self.arch_store.remove_entity(ent);
+        let Some(ent) = self.entity_store.get(ent_id) else {
+            return;
+        };
+        for comp_id in self.arch_store.get_archetype(ent.arch_id).component_ids() {
+            self.removals.component_removed(comp_id, ent_id, self.change_tick);
+        }
+        let (arch_id, arch_row) = (ent.arch_id, ent.arch_row);
+        if let Some(moved) = self.arch_store.remove_entity(ent) {
+            // the last entity of the archetype took the row of the killed entity
+            self.entity_store.move_entity(moved, arch_id, arch_row);
+        }
 
         self.entity_store.kill(ent_id);
+        self.removals.despawned(ent_id, self.change_tick);
     }
     
-    /// Kills an entity without telling the ecs to reuse its id
-    ///
-    /// This means that `world.is_alive` can be used accurately.
-    ///
-    /// You should use `world.free_id(the_killed_entity_id)` later to reuse the memory used by the
-    /// killed entity (64 bits per entity).
+    /// Kills an entity
+    #[deprecated(note = "entity ids are generational, so `kill` keeps `is_alive` accurate")]
     pub fn kill_and_keep(&mut self, ent_id: EntityId) {
-        let ent = &self.entity_store.entities()[ent_id as usize];
-        self.arch_store.remove_entity(ent);
-        
-        self.entity_store.kill(ent_id);
+        self.kill(ent_id);
     }
     
-    /// Frees an entity id, meaning that the `world.is_alive` method will no
-    /// longer be accurate for this entity.
-    ///
-    /// Memory used to store this entity id (64 bits) will be reused.
-    pub fn free_id(&mut self, ent_id: EntityId) {
-        self.entity_store.free_id(ent_id);
-    }
+    /// Does nothing, ids are freed by `kill`
+    #[deprecated(note = "entity ids are generational, so `kill` frees the id")]
+    pub fn free_id(&mut self, _ent_id: EntityId) {}
     
-    /// Check whether an entity is alive. This can only be used accurately when
-    /// `world.kill_and_keep` is used instead of `world.kill`.
-    pub fn is_alive(&mut self, ent_id: EntityId) -> bool {
+    /// Check whether an entity is alive. Returns `false` for ids of killed
+    /// entities, even if their index has been reused.
+    pub fn is_alive(&self, ent_id: EntityId) -> bool {
         self.entity_store.is_alive(ent_id)
     }
     
@@ -62,6 +152,21 @@ impl World {
     pub fn entity_count(&self) -> usize {
         self.entity_store.entity_count()
     }
+    
+    /// The archetype of an entity, `None` if the entity is not alive.
+    ///
+    /// All entities with the same set of components are stored in the same archetype.
+    pub fn archetype_of(&self, entity: EntityId) -> Option<ArchetypeId> {
+        self.entity_store.get(entity).map(|ent| ent.arch_id)
+    }
+    
+    /// The amount of entities in an archetype, 0 if the archetype doesn't exist
+    pub fn archetype_len(&self, arch: ArchetypeId) -> usize {
+        match self.arch_store.archetypes.get(arch as usize) {
+            Some(archetype) => archetype.entities().len(),
+            None => 0
+        }
+    }
 
     // TODO: get component builder for an entity
     // world.get_components(entity_id) // returns (&World, &Entity)
@@ -71,35 +176,69 @@ impl World {
     
     /// Returns the component of type `T` for entity with id `entity`.
     ///
-    /// # Panics
-    /// if the component does not exist for the given entity
-    pub fn get_component<T: Component + 'static>(&self, entity: EntityId) -> &T {
-        let entity = &self.entity_store.entities()[entity as usize];
-        unsafe { self.arch_store.get_archetype(entity.arch_id).get_component::<T>(entity.arch_row) }
+    /// Returns `None` if the entity is not alive or doesn't have the component.
+    pub fn get_component<T: Component + 'static>(&self, entity: EntityId) -> Option<&T> {
+        self.try_get_component(entity).ok()
     }
     
-    /// Returns a mutable referencce to the component of type `T` for entity with id `entity`
+    /// Returns a mutable referencce to the component of type `T` for entity with id `entity`.
+    /// The component is marked as changed.
     ///
-    /// # Panics
-    /// if the component does not exist for the given entity
-    pub fn get_component_mut<T: Component + 'static>(&mut self, entity: EntityId) -> &mut T {
-        let entity = &self.entity_store.entities()[entity as usize];
-        unsafe { self.arch_store.get_archetype_mut(entity.arch_id).get_component_mut::<T>(entity.arch_row) }
+    /// Returns `None` if the entity is not alive or doesn't have the component.
+    pub fn get_component_mut<T: Component + 'static>(&mut self, entity: EntityId) -> Option<&mut T> {
+        self.try_get_component_mut(entity).ok()
     }
     
-    /// Set an entity's component.
+    /// Set an entity's component. The previous value of the component is dropped.
     ///
-    /// # Panics
-    /// if the component does not exist for the given entity
-    pub fn set_component<T: Component + 'static>(&mut self, entity: EntityId, comp: T) {
-        let entity = &self.entity_store.entities()[entity as usize];
-        self.arch_store.get_archetype_mut(entity.arch_id).set_component(entity.arch_row, comp);
+    /// Returns `false` if the entity is not alive or doesn't have the component.
+    pub fn set_component<T: Component + 'static>(&mut self, entity: EntityId, comp: T) -> bool {
+        self.try_set_component(entity, comp).is_ok()
+    }
+    
+    /// Returns the component of type `T` for entity with id `entity`, or
+    /// why it can't be accessed.
+    pub fn try_get_component<T: Component + 'static>(&self, entity: EntityId) -> Result<&T, KiwiError> {
+        let entity = self.entity_store.try_get(entity)?;
+        let archetype = self.arch_store.get_archetype(entity.arch_id);
+        if !archetype.has_component(T::id()) {
+            return Err(KiwiError::MissingComponent { type_name: std::any::type_name::<T>() });
+        }
+        return Ok(unsafe { archetype.get_component::<T>(entity.arch_row) });
+    }
+    
+    /// Returns a mutable reference to the component of type `T` for entity
+    /// with id `entity`, or why it can't be accessed.
+    pub fn try_get_component_mut<T: Component + 'static>(&mut self, entity: EntityId) -> Result<&mut T, KiwiError> {
+        let entity = self.entity_store.try_get(entity)?;
+        let archetype = self.arch_store.get_archetype_mut(entity.arch_id);
+        if !archetype.has_component(T::id()) {
+            return Err(KiwiError::MissingComponent { type_name: std::any::type_name::<T>() });
+        }
+        return Ok(unsafe { archetype.get_component_mut::<T>(entity.arch_row, self.change_tick) });
+    }
+    
+    /// Set an entity's component, or return why it can't be set. The previous
+    /// value of the component is dropped.
+    ///
+    /// This does not add the component if the entity doesn't have it yet,
+    /// use [`insert_component`](World::insert_component) for that.
+    pub fn try_set_component<T: Component + 'static>(&mut self, entity: EntityId, comp: T) -> Result<(), KiwiError> {
+        let entity = self.entity_store.try_get(entity)?;
+        let archetype = self.arch_store.get_archetype_mut(entity.arch_id);
+        if !archetype.has_component(T::id()) {
+            return Err(KiwiError::MissingComponent { type_name: std::any::type_name::<T>() });
+        }
+        archetype.set_component(entity.arch_row, comp, self.change_tick);
+        return Ok(());
     }
     
     /// Check whether an entity contains the given component
     pub fn has_component<C: Component>(&self, entity: EntityId) -> bool {
-        let entity = &self.entity_store.entities()[entity as usize];
-        self.arch_store.get_archetype(entity.arch_id).has_component(C::id())
+        match self.entity_store.get(entity) {
+            Some(entity) => self.arch_store.get_archetype(entity.arch_id).has_component(C::id()),
+            None => false
+        }
     }
     
     /// Returns whether the entity has the specified flag set.
@@ -120,25 +259,505 @@ impl World {
     pub fn unset_flag<F: Flag>(&mut self, entity: EntityId, flag: F) {
         self.entity_store.unset_flag(entity, flag.into())
     }
+    
+    #[inline]
+    pub(crate) fn set_flag_id(&mut self, entity: EntityId, flag: FlagId) {
+        self.entity_store.set_flag(entity, flag)
+    }
+    
+    #[inline]
+    pub(crate) fn unset_flag_id(&mut self, entity: EntityId, flag: FlagId) {
+        self.entity_store.unset_flag(entity, flag)
+    }
+}
+
+// Change detection
+impl World {
+    /// The current change tick. Components that are added or mutably accessed
+    /// are marked with this tick. The tick wraps around when it overflows.
+    #[inline]
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick
+    }
+    
+    /// Changes made after this tick are reported by the `Added` and `Changed`
+    /// filters of queries
+    #[inline]
+    pub fn last_change_tick(&self) -> u32 {
+        self.last_change_tick
+    }
+    
+    /// Stops reporting the changes made so far to `Added` and `Changed` filters,
+    /// [`removed`](World::removed) and [`despawned`](World::despawned), and
+    /// updates the event queues, usually called at the end of a frame. The event
+    /// queues aren't updated again if they were updated at the current change
+    /// tick, e.g. by a schedule that just ran.
+    ///
+    /// Systems run by a [`Schedule`](crate::Schedule) see the changes made since
+    /// they last ran instead, as long as the trackers weren't cleared in the mean time.
+    pub fn clear_trackers(&mut self) {
+        if self.events_updated_at != Some(self.change_tick) {
+            self.update_events();
+        }
+        self.removals.clear(self.change_tick);
+        self.last_change_tick = self.change_tick;
+        self.change_tick = self.change_tick.wrapping_add(1);
+    }
+    
+    /// The entities that lost component `T` since the trackers were last cleared,
+    /// because the component was removed or the entity was killed
+    pub fn removed<T: Component + 'static>(&self) -> impl std::iter::Iterator<Item = EntityId> + '_ {
+        self.removals.removed_since(T::id(), self.last_change_tick)
+    }
+    
+    /// The entities that were killed since the trackers were last cleared
+    pub fn despawned(&self) -> impl std::iter::Iterator<Item = EntityId> + '_ {
+        self.removals.despawned_since(self.last_change_tick)
+    }
+    
+    /// Runs `system`, reporting the changes made after `last_run` to its queries.
+    /// `last_run` is set to the tick the system ran at.
+    pub(crate) fn run_tracked(&mut self, last_run: &mut u32, system: impl FnOnce(&mut World)) {
+        let last_change_tick = std::mem::replace(&mut self.last_change_tick, *last_run);
+        system(self);
+        self.last_change_tick = last_change_tick;
+        *last_run = self.change_tick;
+        self.change_tick = self.change_tick.wrapping_add(1);
+    }
+    
+    /// Forgets the removals that all systems of a schedule have seen, because
+    /// they ran after `tick`
+    #[inline]
+    pub(crate) fn forget_removals(&mut self, tick: u32) {
+        self.removals.clear(tick);
+    }
+    
+    /// Advances the change tick after a batch of systems ran at the current tick
+    #[inline]
+    pub(crate) fn increment_change_tick(&mut self) {
+        self.change_tick = self.change_tick.wrapping_add(1);
+    }
+}
+
+// Commands
+impl World {
+    /// Creates an empty command buffer for this world
+    pub fn commands(&self) -> Commands {
+        Commands::new(self)
+    }
+    
+    /// Applies the commands recorded in `commands` in order, leaving the buffer empty.
+    ///
+    /// # Panics
+    /// If the commands were created for another world
+    pub fn apply_commands(&mut self, commands: &mut Commands) {
+        commands.apply(self);
+    }
+    
+    #[inline]
+    pub(crate) fn id_reserver(&self) -> &Arc<IdReserver> {
+        self.entity_store.id_reserver()
+    }
+    
+    /// Spawns an entity without components with an id reserved by `Commands`
+    pub(crate) fn spawn_reserved(&mut self, ent_id: EntityId) {
+        self.entity_store.spawn_reserved(ent_id);
+        let arch_id = match self.arch_store.get_new_entity_archetype(&[]) {
+            NewEntityResult::NewArchetype(id) | NewEntityResult::OldArchetype(id) => id
+        };
+        let arch_row = self.arch_store.get_archetype_mut(arch_id).new_archrow_id(ent_id);
+        self.entity_store.spawn_with_id(ent_id, arch_id, arch_row);
+    }
+}
+
+// Resources
+impl World {
+    /// Inserts a resource into the world. A resource is a value of which at most
+    /// one instance exists per type, e.g. the delta time or input state.
+    ///
+    /// Returns the previous resource of this type.
+    pub fn insert_resource<R: 'static>(&mut self, resource: R) -> Option<R> {
+        self.resources.insert(resource)
+    }
+    
+    /// Returns the resource of type `R`, or `None` if it doesn't exist
+    pub fn resource<R: 'static>(&self) -> Option<&R> {
+        self.resources.get::<R>()
+    }
+    
+    /// Returns a mutable reference to the resource of type `R`, or `None` if it doesn't exist
+    pub fn resource_mut<R: 'static>(&mut self) -> Option<&mut R> {
+        self.resources.get_mut::<R>()
+    }
+    
+    /// Removes the resource of type `R` from the world and returns it
+    pub fn remove_resource<R: 'static>(&mut self) -> Option<R> {
+        self.resources.remove::<R>()
+    }
+    
+    /// Check whether the world contains a resource of type `R`
+    pub fn has_resource<R: 'static>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+    
+    /// Pointer to the resource of type `R`, used by systems to access resources
+    /// while the world is borrowed by a query.
+    ///
+    /// # Panics
+    /// If the world doesn't contain a resource of type `R`
+    #[doc(hidden)]
+    pub fn resource_ptr<R: 'static>(&self) -> *mut R {
+        self.resources.get_ptr::<R>()
+            .unwrap_or_else(|| panic!("Resource {} does not exist in the world", std::any::type_name::<R>()))
+    }
+}
+
+// Events
+impl World {
+    /// Adds an event queue for events of type `E`, stored as the resource
+    /// `Events<E>`. Does nothing if the event type was already added.
+    pub fn add_event<E: 'static>(&mut self) {
+        if !self.resources.contains::<Events<E>>() {
+            self.resources.insert(Events::<E>::new());
+        }
+        if !self.event_updaters.iter().any(|(id, _)| *id == TypeId::of::<E>()) {
+            self.event_updaters.push((TypeId::of::<E>(), crate::event::update_events::<E>));
+        }
+    }
+    
+    /// Sends an event, adding the event type if it wasn't added yet
+    pub fn send_event<E: 'static>(&mut self, event: E) {
+        if !self.resources.contains::<Events<E>>() {
+            self.add_event::<E>();
+        }
+        let tick = self.change_tick;
+        self.resources.get_mut::<Events<E>>().unwrap().send(event, tick);
+    }
+    
+    /// Returns the events of type `E` sent since the last call to `clear_trackers`,
+    /// or since the current system last ran in a `Schedule`
+    pub fn read_events<E: 'static>(&self) -> EventReader<'_, E> {
+        EventReader::new(self.resources.get::<Events<E>>(), self.last_change_tick)
+    }
+    
+    /// Updates the event queues of all event types, dropping the events that were
+    /// sent before the previous update. Called at the end of every run of a `Schedule`
+    /// and by [`clear_trackers`](World::clear_trackers).
+    pub fn update_events(&mut self) {
+        self.events_updated_at = Some(self.change_tick);
+        for (_, update) in &self.event_updaters {
+            update(&mut self.resources);
+        }
+    }
+    
+    /// Pointer to the event queue of type `E`, used by systems to read and send
+    /// events while the world is borrowed by a query.
+    #[doc(hidden)]
+    #[inline]
+    pub fn events_ptr<E: 'static>(&self) -> Option<*mut Events<E>> {
+        self.resources.get_ptr::<Events<E>>()
+    }
+}
+
+// Serialization
+impl World {
+    /// Registers component `T`, which has `#[component(serialize)]`, so that
+    /// worlds containing it can be loaded. Components are also registered the
+    /// first time their id is requested, but a world loaded at the start of a
+    /// program may contain components that weren't used yet.
+    #[inline]
+    pub fn register_serializable<T: Component + Serializable + 'static>() {
+        // requesting the id registers the serializer under the component's saved name
+        T::id();
+    }
+    
+    /// Saves the entities of the world, their flags and their serializable
+    /// components. Components without `#[component(serialize)]` are skipped, as
+    /// are resources and events.
+    ///
+    /// Entity ids are kept, so components referring to other entities stay valid
+    /// after loading the world.
+    pub fn save(&self, writer: &mut impl std::io::Write) -> Result<(), SerializeError> {
+        // the serializable components of every archetype, ordered by their names
+        // instead of their ids, which depend on the order the components were
+        // registered in and differ between processes
+        let archetypes: Vec<Vec<(ComponentId, ComponentSerializer)>> = self.arch_store.archetypes.iter()
+            .map(|archetype| {
+                let mut components: Vec<(ComponentId, ComponentSerializer)> = archetype.component_ids().into_iter()
+                    .filter_map(|id| serialize::serializer(id).map(|serializer| (id, serializer)))
+                    .collect();
+                components.sort_by_key(|(_, serializer)| serializer.name);
+                components
+            })
+            .collect();
+        let mut names: Vec<&'static str> = archetypes.iter().flatten().map(|(_, serializer)| serializer.name).collect();
+        names.sort();
+        names.dedup();
+        
+        let mut out = Vec::new();
+        out.extend_from_slice(SAVE_MAGIC);
+        SAVE_VERSION.serialize(&mut out);
+        names.iter().map(|name| name.to_string()).collect::<Vec<String>>().serialize(&mut out);
+        self.entity_store.generations().to_vec().serialize(&mut out);
+        self.entity_store.available_ids().to_vec().serialize(&mut out);
+        self.entity_store.flags().to_vec().serialize(&mut out);
+        
+        (archetypes.len() as u32).serialize(&mut out);
+        for (archetype, components) in self.arch_store.archetypes.iter().zip(&archetypes) {
+            let indices: Vec<u32> = components.iter()
+                .map(|(_, serializer)| names.binary_search(&serializer.name).unwrap() as u32)
+                .collect();
+            indices.serialize(&mut out);
+            let rows: Vec<ArchRowId> = archetype.get_arch_rows().collect();
+            (rows.len() as u32).serialize(&mut out);
+            for row in rows {
+                archetype.get_entity_id(row).serialize(&mut out);
+                unsafe { serialize::serialize_row(archetype, row, components, &mut out); }
+            }
+        }
+        
+        writer.write_all(&out)?;
+        return Ok(());
+    }
+    
+    /// Loads a world saved by [`save`](World::save).
+    ///
+    /// All components in the saved world should be registered using
+    /// [`register_serializable`](World::register_serializable), or by requesting
+    /// their id. Otherwise `SerializeError::UnknownComponent` is returned.
+    pub fn load(reader: &mut impl std::io::Read) -> Result<World, SerializeError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut input = bytes.as_slice();
+        if serialize::take(&mut input, SAVE_MAGIC.len()).ok() != Some(SAVE_MAGIC.as_slice()) {
+            return Err(SerializeError::InvalidFormat);
+        }
+        if u32::deserialize(&mut input)? != SAVE_VERSION {
+            return Err(SerializeError::InvalidFormat);
+        }
+        
+        let components: Vec<(ComponentId, ComponentSerializer)> = Vec::<String>::deserialize(&mut input)?.into_iter()
+            .map(|name| serialize::serializer_by_name(&name).ok_or(SerializeError::UnknownComponent(name)))
+            .collect::<Result<_, _>>()?;
+        let generations = Vec::<u32>::deserialize(&mut input)?;
+        let available_ids = Vec::<u32>::deserialize(&mut input)?;
+        let flags = Vec::<Vec<u8>>::deserialize(&mut input)?;
+        
+        let mut world = World::new();
+        let tick = world.change_tick;
+        // whether each index is alive
+        let mut alive = vec![false; generations.len()];
+        let mut entities: Vec<(EntityId, ArchetypeId, ArchRowId)> = Vec::new();
+        
+        let archetype_count = u32::deserialize(&mut input)?;
+        for _ in 0..archetype_count {
+            // the components in the order they are stored in each row
+            let archetype_components: Vec<(ComponentId, ComponentSerializer)> = Vec::<u32>::deserialize(&mut input)?.into_iter()
+                .map(|index| components.get(index as usize).copied().ok_or(SerializeError::InvalidFormat))
+                .collect::<Result<_, _>>()?;
+            // archetypes are identified by their sorted component ids
+            let mut sorted_components = archetype_components.clone();
+            sorted_components.sort_by_key(|(id, _)| *id);
+            let ids: Vec<ComponentId> = sorted_components.iter().map(|(id, _)| *id).collect();
+            if ids.windows(2).any(|pair| pair[0] == pair[1]) {
+                return Err(SerializeError::InvalidFormat);
+            }
+            let arch_id = match world.arch_store.get_new_entity_archetype(&ids) {
+                NewEntityResult::NewArchetype(id) => {
+                    let infos: Vec<ComponentInfo> = sorted_components.iter().map(|(_, serializer)| serializer.info).collect();
+                    world.arch_store.get_archetype_mut(id).init(&ids, &infos);
+                    id
+                }
+                NewEntityResult::OldArchetype(id) => id
+            };
+            
+            let entity_count = u32::deserialize(&mut input)?;
+            for _ in 0..entity_count {
+                let ent_id = EntityId::deserialize(&mut input)?;
+                let index = ent_id.index() as usize;
+                if generations.get(index) != Some(&ent_id.generation()) || alive[index] {
+                    return Err(SerializeError::InvalidFormat);
+                }
+                alive[index] = true;
+                
+                let archetype = world.arch_store.get_archetype_mut(arch_id);
+                let row = archetype.new_archrow_id(ent_id);
+                // the components are read in the order they were saved in, not in the order of their ids
+                serialize::deserialize_row(&mut input, archetype, row, &archetype_components, tick)?;
+                entities.push((ent_id, arch_id, row));
+            }
+        }
+        if !input.is_empty() {
+            return Err(SerializeError::InvalidFormat);
+        }
+        
+        // ids that are reused should be dead, and only be reused once
+        let mut available = alive.clone();
+        for index in &available_ids {
+            match available.get_mut(*index as usize) {
+                Some(used) if !*used => *used = true,
+                _ => return Err(SerializeError::InvalidFormat),
+            }
+        }
+        
+        let alive: Vec<u32> = entities.iter().map(|(ent_id, _, _)| ent_id.index()).collect();
+        world.entity_store = EntityStore::restore(generations, &alive, available_ids, flags);
+        for (ent_id, arch_id, row) in entities {
+            world.entity_store.spawn_with_id(ent_id, arch_id, row);
+        }
+        return Ok(world);
+    }
+}
+
+// Snapshots
+impl World {
+    /// Includes resources of type `R` in snapshots and clones of the world.
+    /// Other resources are not cloned, and are kept when a snapshot is restored.
+    pub fn register_cloneable_resource<R: Clone + 'static>(&mut self) {
+        self.resources.register_clone::<R>();
+    }
+    
+    /// Copies the entities, their components and flags, and the cloneable
+    /// resources, so that the world can be rolled back using [`restore`](World::restore).
+    ///
+    /// # Panics
+    /// If a component doesn't have `#[component(clone)]`
+    pub fn snapshot(&self) -> WorldSnapshot {
+        WorldSnapshot { world: self.clone() }
+    }
+    
+    /// Rolls the world back to a snapshot. Resources that are not cloneable are
+    /// kept. The change tick is not rolled back, the ticks of the restored
+    /// components are moved forward by the ticks that passed since the snapshot
+    /// was taken instead, so that they are as recent relative to the current tick
+    /// as they were when the snapshot was taken. The removals that weren't
+    /// reported to every system yet are kept.
+    ///
+    /// Entities are spawned with the same ids as after the snapshot was taken.
+    /// Entities that commands created before restoring would spawn are not
+    /// spawned, as their ids may be handed out again.
+    pub fn restore(&mut self, snapshot: &WorldSnapshot) {
+        let arch_store = snapshot.world.arch_store.try_clone(snapshot::clone_fn)
+            .unwrap_or_else(|id| snapshot::not_cloneable(id));
+        self.arch_store = arch_store;
+        let offset = self.change_tick.wrapping_sub(snapshot.world.change_tick);
+        for archetype in &mut self.arch_store.archetypes {
+            archetype.offset_ticks(offset);
+        }
+        self.entity_store.restore_from(&snapshot.world.entity_store);
+        self.resources.restore_registered(&snapshot.world.resources);
+        self.removals.merge(&snapshot.world.removals);
+    }
+}
+
+impl Clone for World {
+    /// Clones the entities, their components and flags, and the resources of
+    /// which the type is registered using `register_cloneable_resource`
+    ///
+    /// # Panics
+    /// If a component doesn't have `#[component(clone)]`
+    fn clone(&self) -> Self {
+        Self {
+            entity_store: self.entity_store.clone(),
+            arch_store: self.arch_store.try_clone(snapshot::clone_fn)
+                .unwrap_or_else(|id| snapshot::not_cloneable(id)),
+            resources: self.resources.clone_registered(),
+            removals: self.removals.clone(),
+            event_updaters: self.event_updaters.clone(),
+            events_updated_at: self.events_updated_at,
+            change_tick: self.change_tick,
+            last_change_tick: self.last_change_tick,
+        }
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Queries
 impl World {
     /// Query all entity ids
-    pub fn query_ids<'a>(&'a self) -> impl std::iter::Iterator<Item = EntityId> + 'a {
-        self.entity_store.entities().iter()
-            .enumerate()
-            .map(|(id, _)| id as EntityId)
-            .filter(|id| {
-                self.entity_store.is_alive(*id)
-            })
+    pub fn query_ids(&self) -> impl std::iter::Iterator<Item = EntityId> + '_ {
+        self.entity_store.alive_ids()
     }
     
     #[inline]
     #[doc(hidden)]
-    pub fn query_ids0<'a>(&'a self) -> impl std::iter::Iterator<Item = EntityId> + 'a {
+    pub fn query_ids0(&self) -> impl std::iter::Iterator<Item = EntityId> + '_ {
         self.query_ids()
     }
     
+    #[inline]
+    #[doc(hidden)]
+    pub fn query_filtered_ids0(&self, filters: &[crate::Filter]) -> impl std::iter::Iterator<Item = EntityId> + '_ {
+        let ids: Vec<EntityId> = self.entity_store.alive_ids()
+            .filter(|id| {
+                match self.entity_store.get(*id) {
+                    Some(ent) => {
+                        let archetype = self.arch_store.get_archetype(ent.arch_id);
+                        crate::filter::matches_archetype(filters, archetype)
+                            && crate::filter::matches_row(filters, archetype, &self.entity_store, self.last_change_tick, ent.arch_row)
+                    }
+                    None => false
+                }
+            })
+            .collect();
+        ids.into_iter()
+    }
+    
+    /// Iterates the archetypes matching the query, yielding the ids of their
+    /// entities and a slice of each requested component per archetype, e.g.
+    /// `(&[EntityId], &[Position], &[Velocity])` for `(Position, Velocity)`.
+    /// The slices are indexed by the same row.
+    pub fn query_chunks<'a, Q: ReadOnlyChunkQuery>(&'a self) -> impl std::iter::Iterator<Item = Q::Chunk<'a>> + 'a {
+        let components = Q::components();
+        self.arch_store.get_archetypes_with(&Q::required())
+            .into_iter()
+            .map(|arch_id| self.arch_store.get_archetype(arch_id))
+            .filter(|archetype| !archetype.entities().is_empty())
+            .map(move |archetype| {
+                let columns = crate::query::chunk_columns(archetype, &components);
+                unsafe { Q::fetch_chunk(archetype.entities(), &columns) }
+            })
+    }
+    
+    /// The amount of entities matching the query, counted per archetype
+    pub fn query_count<Q: ChunkQuery>(&self) -> usize {
+        self.arch_store.get_archetypes_with(&Q::required())
+            .into_iter()
+            .map(|arch_id| self.arch_store.get_archetype(arch_id).entities().len())
+            .sum()
+    }
+    
+    /// Like `query_chunks`, but components requested as `T` or `Write<T>` are
+    /// yielded as mutable slices. All components in these slices are marked as changed.
+    ///
+    /// # Panics
+    /// If a component that is written to is requested more than once
+    pub fn query_chunks_mut<'a, Q: ChunkQuery>(&'a mut self) -> impl std::iter::Iterator<Item = Q::ChunkMut<'a>> + 'a {
+        let components = Q::components();
+        crate::query::assert_unique_components(&components);
+        let change_tick = self.change_tick;
+        let arch_store = &self.arch_store;
+        arch_store.get_archetypes_with(&Q::required())
+            .into_iter()
+            .map(|arch_id| arch_store.get_archetype(arch_id))
+            .filter(|archetype| !archetype.entities().is_empty())
+            .map(move |archetype| {
+                for (id, writes) in &components {
+                    if let (true, Some(ticks)) = (*writes, archetype.component_ticks(*id)) {
+                        ticks.iter().for_each(|ticks| ticks.set_changed(change_tick));
+                    }
+                }
+                let columns = crate::query::chunk_columns(archetype, &components);
+                // the world is borrowed mutably and every archetype is yielded
+                // once, so the components can't be aliased
+                unsafe { Q::fetch_chunk_mut(archetype.entities(), &columns) }
+            })
+    }
+    
     kiwi_internal_macros::gen_query!();
 }
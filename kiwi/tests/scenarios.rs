@@ -24,25 +24,32 @@ fn spawn_fighter_game() {
     }
 
     #[derive(Component, Debug)]
+    #[allow(dead_code)]
     pub struct Position(pub Vec2);
 
     #[derive(Component)]
+    #[allow(dead_code)]
     pub struct Velocity(pub Vec2);
 
     #[derive(Component)]
+    #[allow(dead_code)]
     pub struct Speed(pub f32);
 
     #[derive(Component)]
+    #[allow(dead_code)]
     pub struct DeployPosition(pub Vec2);
 
     #[derive(Component)]
+    #[allow(dead_code)]
     /// Entity that is being targeted for fighting
     pub struct Target(pub Option<EntityId>);
 
     #[derive(Component)]
+    #[allow(dead_code)]
     pub struct Sprite(pub usize);
 
     #[derive(Component)]
+    #[allow(dead_code)]
     pub struct Bounds(pub Vec2);
     
     assert_ne!(Bounds::id(), Velocity::id());
@@ -64,13 +71,9 @@ fn spawn_fighter_game() {
             Sprite(0),
         );
         
-        assert_eq!(*Bounds::get_archetypes(), vec![0]);
-
-        let id = id;
-    
         world.set_flag(id, Flags::Fighter);
         
-        assert_eq!(world.has_flag(id, Flags::Fighter), true);
+        assert!(world.has_flag(id, Flags::Fighter));
     
         id
     }
@@ -81,7 +84,7 @@ fn spawn_fighter_game() {
     
     for i in 0..1000 {
         let id = spawn_fighter(&mut world, Vec2::new(415.896, 500.6));
-        assert_eq!(id, i);
+        assert_eq!(id.index(), i);
     }
 }
 
@@ -98,6 +101,6 @@ fn reuse_ids() {
     let id = spawn_entity!(world, Vel { x: 0, y: 10 });
     world.kill(id);
     let id = spawn_entity!(world, Pos { x: 10, y: 11 }, Vel { x: 10, y: 10 });
-    assert_eq!(*world.get_component::<Pos>(id), Pos { x: 10, y: 11 });
-    assert_eq!(*world.get_component::<Vel>(id), Vel { x: 10, y: 10 });
+    assert_eq!(world.get_component::<Pos>(id), Some(&Pos { x: 10, y: 11 }));
+    assert_eq!(world.get_component::<Vel>(id), Some(&Vel { x: 10, y: 10 }));
 }
@@ -49,6 +49,7 @@ fn spawn_entity() {
 macro_rules! comp {
     ($name: ident) => {
         #[derive(Component)]
+        #[allow(dead_code)]
         struct $name(u32);
     }
 }
@@ -110,7 +111,7 @@ fn get_component() {
     
     let mut world = World::new();
     let ent_id = world.spawn_entity1(Pos{x: 1, y: 0});
-    let comp: &Pos = world.get_component(ent_id);
+    let comp: &Pos = world.get_component(ent_id).unwrap();
     assert_eq!(*comp, Pos{x: 1, y: 0});
 }
 
@@ -180,13 +181,13 @@ fn mut_query_after_set_component() {
         
         assert_eq!(*pos, Pos { x: 4, y: 0 });
         
-        let current_pos = unsafe { (*world_ptr).get_component::<Pos>(id) };
+        let current_pos = unsafe { (*world_ptr).get_component::<Pos>(id) }.unwrap();
         
         assert_eq!(*pos, *current_pos);
         
         unsafe { (*world_ptr).set_component(id, Pos { x: 6, y: 10 }) };
         
-        assert_eq!(*unsafe { (*world_ptr).get_component::<Pos>(id) }, Pos { x: 6, y: 10 });
+        assert_eq!(*unsafe { (*world_ptr).get_component::<Pos>(id) }.unwrap(), Pos { x: 6, y: 10 });
         assert_eq!(*pos, Pos { x: 6, y: 10 });
     });
 }
@@ -269,7 +270,7 @@ fn system_macro_ids() {
     
     #[system(id: EntityId, pos: Pos)]
     fn test_macro(world: &World) {
-        assert_eq!(id, 0);
+        assert_eq!(id.index(), 0);
         assert_eq!(*pos, Pos { x: 0, y: 1 });
     }
     
@@ -300,9 +301,9 @@ fn query_ids() {
     query_ids_spawn_entity!(world);
     query_ids_spawn_entity!(world);
     
-    let ids: Vec<EntityId> = query!(world, EntityId, Pos, Vel)
+    let ids: Vec<u32> = query!(world, EntityId, Pos, Vel)
         .map(|(id, _, _)| {
-            id
+            id.index()
         }).collect();
     assert_eq!(ids, vec![0, 2, 3, 4, 5]);
 }
@@ -364,6 +365,1662 @@ fn reuse_entity_id_flags() {
     assert!(!world.has_flag(id, Flags::One));
 }
 
+#[test]
+fn insert_component() {
+    pos_comp!();
+    vel_comp!();
+    
+    let mut world = World::new();
+    let id1 = spawn_entity!(world, Pos { x: 0, y: 1 });
+    let id2 = spawn_entity!(world, Pos { x: 2, y: 3 });
+    
+    world.insert_component(id1, Vel { x: 4, y: 5 });
+    assert!(world.has_component::<Vel>(id1));
+    assert!(!world.has_component::<Vel>(id2));
+    assert_eq!(world.get_component::<Pos>(id1), Some(&Pos { x: 0, y: 1 }));
+    assert_eq!(world.get_component::<Vel>(id1), Some(&Vel { x: 4, y: 5 }));
+    
+    let ids: Vec<EntityId> = query!(world, EntityId, Pos, Vel).map(|(id, _, _)| id).collect();
+    assert_eq!(ids, vec![id1]);
+    assert_eq!(query!(world, Pos).count(), 2);
+    
+    // overwrites an existing component
+    world.insert_component(id1, Vel { x: 6, y: 7 });
+    assert_eq!(world.get_component::<Vel>(id1), Some(&Vel { x: 6, y: 7 }));
+    assert_eq!(query!(world, Vel).count(), 1);
+}
+
+#[test]
+fn remove_component() {
+    pos_comp!();
+    vel_comp!();
+    
+    let mut world = World::new();
+    let id1 = spawn_entity!(world, Pos { x: 0, y: 1 }, Vel { x: 2, y: 3 });
+    let id2 = spawn_entity!(world, Pos { x: 4, y: 5 }, Vel { x: 6, y: 7 });
+    
+    assert_eq!(world.remove_component::<Vel>(id1), Some(Vel { x: 2, y: 3 }));
+    assert_eq!(world.remove_component::<Vel>(id1), None);
+    assert!(!world.has_component::<Vel>(id1));
+    assert_eq!(world.get_component::<Pos>(id1), Some(&Pos { x: 0, y: 1 }));
+    assert_eq!(world.get_component::<Vel>(id2), Some(&Vel { x: 6, y: 7 }));
+    
+    let ids: Vec<EntityId> = query!(world, EntityId, Pos, Vel).map(|(id, _, _)| id).collect();
+    assert_eq!(ids, vec![id2]);
+    
+    // moving back reuses the original archetype
+    world.insert_component(id1, Vel { x: 8, y: 9 });
+    let comps: Vec<(EntityId, &Vel)> = query!(world, EntityId, Vel).collect();
+    assert_eq!(comps.len(), 2);
+    assert!(comps.contains(&(id1, &Vel { x: 8, y: 9 })));
+    assert!(comps.contains(&(id2, &Vel { x: 6, y: 7 })));
+}
+
+#[test]
+fn drop_components() {
+    use std::rc::Rc;
+    
+    // the handle is only dropped, never read
+    #[derive(Component)]
+    #[allow(dead_code)]
+    struct Handle(Rc<()>);
+    
+    let rc = Rc::new(());
+    let mut world = World::new();
+    
+    let id1 = spawn_entity!(world, Handle(rc.clone()));
+    let id2 = spawn_entity!(world, Handle(rc.clone()));
+    let _id3 = spawn_entity!(world, Handle(rc.clone()));
+    assert_eq!(Rc::strong_count(&rc), 4);
+    
+    world.kill(id1);
+    assert_eq!(Rc::strong_count(&rc), 3);
+    
+    // overwriting drops the old value
+    world.set_component(id2, Handle(rc.clone()));
+    assert_eq!(Rc::strong_count(&rc), 3);
+    
+    // removed components are returned, not dropped
+    let handle = world.remove_component::<Handle>(id2);
+    assert_eq!(Rc::strong_count(&rc), 3);
+    drop(handle);
+    assert_eq!(Rc::strong_count(&rc), 2);
+    
+    drop(world);
+    assert_eq!(Rc::strong_count(&rc), 1);
+}
+
+#[test]
+fn aligned_components() {
+    #[derive(Component, Debug, PartialEq)]
+    #[repr(align(32))]
+    struct Aligned([f32; 4]);
+    
+    #[derive(Component, Debug, PartialEq)]
+    struct Small(u8);
+    
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..100)
+        .map(|i| spawn_entity!(world, Small(i as u8), Aligned([i as f32; 4])))
+        .collect();
+    
+    for (i, id) in ids.iter().enumerate() {
+        let comp: &Aligned = world.get_component(*id).unwrap();
+        assert_eq!(comp as *const Aligned as usize % 32, 0);
+        assert_eq!(*comp, Aligned([i as f32; 4]));
+    }
+    query!(world, Aligned).for_each(|comp| {
+        assert_eq!(comp as *const Aligned as usize % 32, 0);
+    });
+}
+
+#[test]
+fn independent_worlds() {
+    pos_comp!();
+    vel_comp!();
+    
+    let mut world1 = World::new();
+    let mut world2 = World::new();
+    
+    spawn_entity!(world1, Vel { x: 0, y: 0 });
+    spawn_entity!(world1, Pos { x: 1, y: 1 });
+    spawn_entity!(world2, Pos { x: 2, y: 2 });
+    spawn_entity!(world2, Pos { x: 3, y: 3 }, Vel { x: 4, y: 4 });
+    
+    let comps: Vec<&Pos> = query!(world1, Pos).collect();
+    assert_eq!(comps, vec![&Pos { x: 1, y: 1 }]);
+    
+    let comps: Vec<&Pos> = query!(world2, Pos).collect();
+    assert_eq!(comps, vec![&Pos { x: 2, y: 2 }, &Pos { x: 3, y: 3 }]);
+    
+    let comps: Vec<(&Pos, &Vel)> = query!(world2, Pos, Vel).collect();
+    assert_eq!(comps, vec![(&Pos { x: 3, y: 3 }, &Vel { x: 4, y: 4 })]);
+    assert_eq!(query!(world1, Pos, Vel).count(), 0);
+}
+
+#[test]
+fn generic_components() {
+    #[derive(Component, Debug, PartialEq)]
+    struct Value<T>(T);
+    
+    #[derive(Component, Debug, PartialEq)]
+    struct Name<'a>(&'a str);
+    
+    let mut world = World::new();
+    let id = spawn_entity!(world, Value(1u32), Value(2u64), Name("kiwi"));
+    
+    assert_eq!(world.get_component::<Value<u32>>(id), Some(&Value(1)));
+    assert_eq!(world.get_component::<Value<u64>>(id), Some(&Value(2)));
+    assert_eq!(world.get_component::<Name>(id), Some(&Name("kiwi")));
+    assert_eq!(query!(world, Value<u32>).count(), 1);
+}
+
+#[test]
+fn stale_entity_ids() {
+    pos_comp!();
+    
+    #[flags]
+    enum Flags {
+        Player
+    }
+    
+    let mut world = World::new();
+    let old = spawn_entity!(world, Pos { x: 0, y: 1 });
+    world.set_flag(old, Flags::Player);
+    world.kill(old);
+    assert!(!world.is_alive(old));
+    
+    let new = spawn_entity!(world, Pos { x: 2, y: 3 });
+    assert_eq!(new.index(), old.index());
+    assert!(world.is_alive(new));
+    assert!(!world.is_alive(old));
+    
+    assert_eq!(world.get_component::<Pos>(old), None);
+    assert!(world.get_component_mut::<Pos>(old).is_none());
+    assert!(!world.set_component(old, Pos { x: 4, y: 5 }));
+    assert!(!world.has_component::<Pos>(old));
+    world.set_flag(old, Flags::Player);
+    assert!(!world.has_flag(old, Flags::Player));
+    assert!(!world.has_flag(new, Flags::Player));
+    
+    // killing a stale id doesn't kill the new entity
+    world.kill(old);
+    assert!(world.is_alive(new));
+    assert_eq!(world.get_component::<Pos>(new), Some(&Pos { x: 2, y: 3 }));
+}
+
+#[test]
+fn try_get_component() {
+    pos_comp!();
+    vel_comp!();
+    
+    let mut world = World::new();
+    let id = spawn_entity!(world, Pos { x: 0, y: 1 });
+    
+    assert_eq!(world.try_get_component::<Pos>(id), Ok(&Pos { x: 0, y: 1 }));
+    assert_eq!(
+        world.try_get_component::<Vel>(id),
+        Err(KiwiError::MissingComponent { type_name: std::any::type_name::<Vel>() })
+    );
+    
+    world.try_get_component_mut::<Pos>(id).unwrap().x = 2;
+    assert_eq!(world.try_set_component(id, Pos { x: 3, y: 4 }), Ok(()));
+    assert_eq!(world.get_component::<Pos>(id), Some(&Pos { x: 3, y: 4 }));
+    assert!(world.try_set_component(id, Vel { x: 0, y: 0 }).is_err());
+    
+    world.kill(id);
+    assert_eq!(world.try_get_component::<Pos>(id), Err(KiwiError::EntityDead));
+    assert_eq!(world.try_get_component_mut::<Pos>(id).err(), Some(KiwiError::EntityDead));
+    assert_eq!(world.try_set_component(id, Pos { x: 0, y: 0 }), Err(KiwiError::EntityDead));
+    
+    // an id from another world with an index that doesn't exist in this one
+    let mut other_world = World::new();
+    let foreign = (0..4).map(|_| spawn_entity!(other_world, Pos { x: 0, y: 0 })).last().unwrap();
+    assert_eq!(world.try_get_component::<Pos>(foreign), Err(KiwiError::NoSuchEntity));
+    assert_eq!(world.get_component::<Pos>(foreign), None);
+}
+
+#[test]
+fn zero_sized_components() {
+    pos_comp!();
+    
+    #[derive(Debug, PartialEq, Component)]
+    struct Marker {}
+    
+    let mut world = World::new();
+    let id = spawn_entity!(world, Pos { x: 0, y: 1 }, Marker {});
+    
+    assert_eq!(world.get_component::<Marker>(id), Some(&Marker {}));
+    assert_eq!(world.try_get_component_mut::<Marker>(id), Ok(&mut Marker {}));
+    assert_eq!(query!(world, Marker).count(), 1);
+    assert_eq!(query_mut!(world, Pos, Marker).count(), 1);
+}
+
+#[test]
+fn query_filters() {
+    pos_comp!();
+    vel_comp!();
+    
+    #[derive(Component)]
+    struct Frozen {}
+    
+    #[derive(Component)]
+    struct Player {}
+    
+    #[flags]
+    enum Flags {
+        Enemy,
+        Boss,
+    }
+    
+    let mut world = World::new();
+    let moving = spawn_entity!(world, Pos { x: 0, y: 0 }, Vel { x: 1, y: 1 });
+    let frozen = spawn_entity!(world, Pos { x: 1, y: 1 }, Vel { x: 1, y: 1 }, Frozen {});
+    let player = spawn_entity!(world, Pos { x: 2, y: 2 }, Vel { x: 1, y: 1 }, Player {});
+    let enemy = spawn_entity!(world, Pos { x: 3, y: 3 }, Vel { x: 1, y: 1 });
+    let boss = spawn_entity!(world, Pos { x: 4, y: 4 });
+    world.set_flag(enemy, Flags::Enemy);
+    world.set_flag(boss, Flags::Boss);
+    
+    let ids: Vec<EntityId> = query!(world, EntityId, Pos, Vel, without Frozen).map(|(id, _, _)| id).collect();
+    assert_eq!(ids.len(), 3);
+    assert!(!ids.contains(&frozen));
+    
+    let ids: Vec<EntityId> = query!(world, EntityId, Pos, with Player).map(|(id, _)| id).collect();
+    assert_eq!(ids, vec![player]);
+    
+    let ids: Vec<EntityId> = query!(world, EntityId, Pos, Vel, without Frozen, flag Flags::Enemy).map(|(id, _, _)| id).collect();
+    assert_eq!(ids, vec![enemy]);
+    
+    let mut ids: Vec<EntityId> = query!(world, EntityId, Pos, or(with Player, flag Flags::Enemy, flag Flags::Boss),).map(|(id, _)| id).collect();
+    ids.sort();
+    let mut expected = vec![player, enemy, boss];
+    expected.sort();
+    assert_eq!(ids, expected);
+    
+    let ids: Vec<EntityId> = query!(world, EntityId, flag Flags::Boss).collect();
+    assert_eq!(ids, vec![boss]);
+    
+    assert_eq!(query!(world, Pos, flag Flags::Enemy).next(), Some(&Pos { x: 3, y: 3 }));
+    
+    query_mut!(world, Vel, without Frozen, without Player).for_each(|vel| vel.x = 0);
+    assert_eq!(world.get_component::<Vel>(moving).unwrap().x, 0);
+    assert_eq!(world.get_component::<Vel>(enemy).unwrap().x, 0);
+    assert_eq!(world.get_component::<Vel>(frozen).unwrap().x, 1);
+    assert_eq!(world.get_component::<Vel>(player).unwrap().x, 1);
+    
+    #[system(pos: Pos, without Frozen, flag Flags::Enemy)]
+    fn move_enemies(world: &mut World) {
+        pos.x += 10;
+    }
+    
+    #[system(id: EntityId, pos: Pos, or(with Player, flag Flags::Boss))]
+    fn collect_ids(world: &World, ids: &mut Vec<EntityId>) {
+        assert!(pos.x > 0);
+        ids.push(id);
+    }
+    
+    move_enemies(&mut world);
+    assert_eq!(world.get_component::<Pos>(enemy), Some(&Pos { x: 13, y: 3 }));
+    assert_eq!(world.get_component::<Pos>(moving), Some(&Pos { x: 0, y: 0 }));
+    
+    let mut ids = Vec::new();
+    collect_ids(&world, &mut ids);
+    ids.sort();
+    let mut expected = vec![player, boss];
+    expected.sort();
+    assert_eq!(ids, expected);
+}
+
+#[test]
+fn optional_components() {
+    pos_comp!();
+    vel_comp!();
+    
+    #[derive(Debug, PartialEq, Component)]
+    struct Sprite {
+        id: u32
+    }
+    
+    let mut world = World::new();
+    let with_sprite = spawn_entity!(world, Pos { x: 0, y: 0 }, Sprite { id: 1 });
+    let without_sprite = spawn_entity!(world, Pos { x: 1, y: 1 }, Vel { x: 0, y: 0 });
+    spawn_entity!(world, Vel { x: 0, y: 0 }, Sprite { id: 2 });
+    
+    let mut result: Vec<(EntityId, Option<&Sprite>)> = query!(world, EntityId, Pos, Option<Sprite>)
+        .map(|(id, _pos, sprite)| (id, sprite))
+        .collect();
+    result.sort_by_key(|(id, _)| *id);
+    assert_eq!(result, vec![(with_sprite, Some(&Sprite { id: 1 })), (without_sprite, None)]);
+    
+    query_mut!(world, Pos, Option<Sprite>).for_each(|(pos, sprite)| {
+        if let Some(sprite) = sprite {
+            sprite.id += 10;
+            pos.x += 10;
+        }
+    });
+    assert_eq!(world.get_component::<Sprite>(with_sprite), Some(&Sprite { id: 11 }));
+    assert_eq!(world.get_component::<Pos>(with_sprite), Some(&Pos { x: 10, y: 0 }));
+    assert_eq!(world.get_component::<Pos>(without_sprite), Some(&Pos { x: 1, y: 1 }));
+    
+    // only optional components matches every entity
+    assert_eq!(query!(world, Option<Vel>).filter(|vel| vel.is_some()).count(), 2);
+    assert_eq!(query!(world, Option<Vel>).count(), 3);
+    
+    #[system(pos: Pos, sprite: Option<Sprite>)]
+    fn render(world: &World, rendered: &mut Vec<(u32, Option<u32>)>) {
+        rendered.push((pos.x, sprite.map(|sprite| sprite.id)));
+    }
+    
+    let mut rendered = Vec::new();
+    render(&world, &mut rendered);
+    rendered.sort();
+    assert_eq!(rendered, vec![(1, None), (10, Some(11))]);
+}
+
+#[test]
+#[should_panic(expected = "more than once in a mutable query")]
+fn query_mut_duplicate_components() {
+    pos_comp!();
+    
+    let mut world = World::new();
+    spawn_entity!(world, Pos { x: 0, y: 0 });
+    
+    let _ = world.query_mut2::<Pos, Pos>();
+}
+
+#[test]
+#[should_panic(expected = "more than once in a mutable query")]
+fn query_mut_duplicate_optional_component() {
+    pos_comp!();
+    
+    let mut world = World::new();
+    spawn_entity!(world, Pos { x: 0, y: 0 });
+    
+    let _ = world.query_mut_ids2::<Pos, Option<Pos>>();
+}
+
+#[test]
+fn mixed_access_queries() {
+    pos_comp!();
+    vel_comp!();
+    
+    let mut world = World::new();
+    let id = spawn_entity!(world, Pos { x: 0, y: 0 }, Vel { x: 1, y: 2 });
+    
+    query!(world, &mut Pos, &Vel).for_each(|(pos, vel): (&mut Pos, &Vel)| {
+        pos.x += vel.x;
+        pos.y += vel.y;
+    });
+    assert_eq!(world.get_component::<Pos>(id), Some(&Pos { x: 1, y: 2 }));
+    
+    query_mut!(world, EntityId, Pos, &Vel, Option<&Vel>).for_each(|(_, pos, vel, _)| {
+        pos.x += vel.x;
+    });
+    assert_eq!(world.get_component::<Pos>(id), Some(&Pos { x: 2, y: 2 }));
+    
+    // reading the same component twice is allowed
+    assert_eq!(query_mut!(world, &Pos, &Pos).count(), 1);
+    assert_eq!(world.query_mut2::<Read<Pos>, Option<Read<Pos>>>().count(), 1);
+    
+    let shared: Vec<(&Pos, &Vel)> = query!(world, &Pos, &Vel).collect();
+    assert_eq!(shared, vec![(&Pos { x: 2, y: 2 }, &Vel { x: 1, y: 2 })]);
+    
+    #[system(pos: &mut Pos, vel: &Vel)]
+    fn move_entities(world: &mut World) {
+        pos.x += vel.x;
+    }
+    
+    #[system(pos: &Pos)]
+    fn sum_x(world: &World, sum: &mut u32) {
+        *sum += pos.x;
+    }
+    
+    move_entities(&mut world);
+    let mut sum = 0;
+    sum_x(&world, &mut sum);
+    assert_eq!(sum, 3);
+}
+
+#[test]
+#[should_panic(expected = "more than once in a mutable query")]
+fn query_mut_read_write_same_component() {
+    pos_comp!();
+    
+    let mut world = World::new();
+    spawn_entity!(world, Pos { x: 0, y: 0 });
+    
+    let _ = world.query_mut2::<Read<Pos>, Write<Pos>>();
+}
+
+#[test]
+fn resources() {
+    #[derive(Debug, PartialEq)]
+    struct DeltaTime(u32);
+    
+    let mut world = World::new();
+    assert!(!world.has_resource::<DeltaTime>());
+    assert_eq!(world.resource::<DeltaTime>(), None);
+    
+    assert_eq!(world.insert_resource(DeltaTime(1)), None);
+    assert!(world.has_resource::<DeltaTime>());
+    assert_eq!(world.resource::<DeltaTime>(), Some(&DeltaTime(1)));
+    
+    world.resource_mut::<DeltaTime>().unwrap().0 = 2;
+    assert_eq!(world.insert_resource(DeltaTime(3)), Some(DeltaTime(2)));
+    assert_eq!(world.remove_resource::<DeltaTime>(), Some(DeltaTime(3)));
+    assert_eq!(world.remove_resource::<DeltaTime>(), None);
+    assert!(!world.has_resource::<DeltaTime>());
+}
+
+#[test]
+fn system_resources() {
+    pos_comp!();
+    
+    struct DeltaTime(u32);
+    struct Counter(u32);
+    
+    #[system(pos: Pos, res dt: DeltaTime, res mut counter: Counter)]
+    fn move_entities(world: &mut World) {
+        pos.x += dt.0;
+        counter.0 += 1;
+    }
+    
+    #[system(pos: Pos, res dt: DeltaTime)]
+    fn sum(world: &World, total: &mut u32) {
+        *total += pos.x * dt.0;
+    }
+    
+    let mut world = World::new();
+    spawn_entity!(world, Pos { x: 0, y: 0 });
+    spawn_entity!(world, Pos { x: 1, y: 0 });
+    world.insert_resource(DeltaTime(2));
+    world.insert_resource(Counter(0));
+    
+    move_entities(&mut world);
+    assert_eq!(world.resource::<Counter>().unwrap().0, 2);
+    
+    let mut total = 0;
+    sum(&world, &mut total);
+    assert_eq!(total, (2 + 3) * 2);
+}
+
+#[test]
+#[should_panic(expected = "does not exist in the world")]
+fn system_missing_resource() {
+    pos_comp!();
+    
+    struct DeltaTime(u32);
+    
+    #[system(pos: Pos, res dt: DeltaTime)]
+    fn move_entities(world: &mut World) {
+        pos.x += dt.0;
+    }
+    
+    let mut world = World::new();
+    move_entities(&mut world);
+}
+
+#[test]
+fn commands() {
+    pos_comp!();
+    vel_comp!();
+    
+    #[flags]
+    enum Flags {
+        Player
+    }
+    
+    let mut world = World::new();
+    let existing = spawn_entity!(world, Pos { x: 0, y: 0 }, Vel { x: 1, y: 1 });
+    
+    let mut commands = world.commands();
+    let reserved = commands.spawn();
+    commands.insert(reserved, Pos { x: 1, y: 1 });
+    commands.set_flag(reserved, Flags::Player);
+    commands.remove::<Vel>(existing);
+    assert_eq!(commands.len(), 4);
+    assert!(!world.is_alive(reserved));
+    
+    // spawning while ids are reserved doesn't reuse the reserved id
+    let spawned = spawn_entity!(world, Pos { x: 2, y: 2 });
+    assert_ne!(spawned, reserved);
+    assert!(!world.is_alive(reserved));
+    assert_eq!(world.entity_count(), 2);
+    assert_eq!(query!(world, Pos).count(), 2);
+    
+    world.apply_commands(&mut commands);
+    assert!(commands.is_empty());
+    assert!(world.is_alive(reserved));
+    assert_eq!(world.get_component::<Pos>(reserved), Some(&Pos { x: 1, y: 1 }));
+    assert!(world.has_flag(reserved, Flags::Player));
+    assert!(!world.has_component::<Vel>(existing));
+    assert_eq!(world.entity_count(), 3);
+    
+    commands.kill(spawned);
+    commands.add(|world: &mut World| {
+        world.insert_resource(5u32);
+    });
+    world.apply_commands(&mut commands);
+    assert!(!world.is_alive(spawned));
+    assert_eq!(world.resource::<u32>(), Some(&5));
+}
+
+#[test]
+fn system_commands() {
+    pos_comp!();
+    
+    #[system(id: EntityId, pos: Pos, commands cmds)]
+    fn split(world: &mut World) {
+        if pos.x > 0 {
+            let child = cmds.spawn();
+            cmds.insert(child, Pos { x: pos.x - 1, y: pos.y });
+            cmds.kill(id);
+        }
+    }
+    
+    let mut world = World::new();
+    let parent = spawn_entity!(world, Pos { x: 2, y: 0 });
+    split(&mut world);
+    
+    assert!(!world.is_alive(parent));
+    let positions: Vec<&Pos> = query!(world, Pos).collect();
+    assert_eq!(positions, vec![&Pos { x: 1, y: 0 }]);
+}
+
+#[test]
+#[should_panic(expected = "only be applied to the world they were created for")]
+fn commands_other_world() {
+    let world = World::new();
+    let mut other_world = World::new();
+    let mut commands = world.commands();
+    commands.spawn();
+    other_world.apply_commands(&mut commands);
+}
+
+#[test]
+fn schedule() {
+    struct Log(Vec<&'static str>);
+    struct Paused(bool);
+    
+    fn input(world: &mut World) {
+        world.resource_mut::<Log>().unwrap().0.push("input");
+    }
+    fn movement(world: &mut World) {
+        world.resource_mut::<Log>().unwrap().0.push("movement");
+    }
+    fn physics(world: &mut World) {
+        world.resource_mut::<Log>().unwrap().0.push("physics");
+    }
+    fn render(world: &World) {
+        assert!(world.has_resource::<Log>());
+    }
+    fn cleanup(world: &mut World) {
+        world.resource_mut::<Log>().unwrap().0.push("cleanup");
+    }
+    
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::PostUpdate, cleanup);
+    schedule.add_system(Stage::Render, render);
+    schedule.add_system(Stage::Update, physics).after("movement");
+    schedule.add_system(Stage::Update, movement)
+        .after("input")
+        .run_if(|world: &World| !world.resource::<Paused>().unwrap().0);
+    schedule.add_system(Stage::Update, |world: &mut World| {
+        world.resource_mut::<Log>().unwrap().0.push("read_input");
+    }).label("input").before("physics");
+    
+    let mut world = World::new();
+    world.insert_resource(Log(Vec::new()));
+    world.insert_resource(Paused(false));
+    
+    assert_eq!(schedule.build(), Ok(()));
+    schedule.run(&mut world);
+    assert_eq!(world.resource::<Log>().unwrap().0, vec!["read_input", "movement", "physics", "cleanup"]);
+    
+    world.resource_mut::<Log>().unwrap().0.clear();
+    world.resource_mut::<Paused>().unwrap().0 = true;
+    schedule.run(&mut world);
+    assert_eq!(world.resource::<Log>().unwrap().0, vec!["read_input", "physics", "cleanup"]);
+    
+    // systems added later still respect the constraints
+    schedule.add_system(Stage::PreUpdate, input);
+    world.resource_mut::<Log>().unwrap().0.clear();
+    schedule.run(&mut world);
+    assert_eq!(world.resource::<Log>().unwrap().0, vec!["input", "read_input", "physics", "cleanup"]);
+}
+
+#[test]
+fn schedule_errors() {
+    fn a(_world: &mut World) {}
+    fn b(_world: &mut World) {}
+    fn c(_world: &mut World) {}
+    
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::Update, a).after("c");
+    schedule.add_system(Stage::Update, b).after("a");
+    schedule.add_system(Stage::Update, c).after("b");
+    let err = schedule.build().unwrap_err();
+    assert_eq!(err, ScheduleError::Cycle { stage: Stage::Update, systems: vec!["a", "b", "c"] });
+    assert_eq!(err.to_string(), "the ordering constraints of systems in stage Update form a cycle: a -> b -> c -> a");
+    
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::Update, a).before("d");
+    assert_eq!(schedule.build(), Err(ScheduleError::UnknownLabel { system: "a", label: "d" }));
+}
+
+#[test]
+fn system_access() {
+    pos_comp!();
+    vel_comp!();
+    
+    #[derive(Component)]
+    struct Shared(std::rc::Rc<u32>);
+    struct Gravity(u32);
+    struct Seed(std::rc::Rc<u32>);
+    
+    #[system(pos: &mut Pos, vel: &Vel, res gravity: Gravity)]
+    fn fall(world: &mut World) {
+        pos.y += vel.y + gravity.0;
+    }
+    
+    #[system(pos: Pos, shared: Option<Shared>)]
+    fn uses_rc(world: &mut World) {
+        pos.x += shared.map(|shared| *shared.0).unwrap_or(0);
+    }
+    
+    #[system(pos: &mut Pos, res seed: Seed)]
+    fn scatter(world: &mut World) {
+        pos.x += *seed.0;
+    }
+    
+    #[system(pos: Pos)]
+    fn print(world: &World) {
+        println!("{:?}", pos);
+    }
+    
+    #[system(id: EntityId, pos: Pos, commands cmds)]
+    fn remove_far(world: &mut World) {
+        if pos.x > 100 {
+            cmds.kill(id);
+        }
+    }
+    
+    let info = fall_info();
+    assert_eq!(info.name(), "fall");
+    assert_eq!(info.access(), &SystemAccess::new().write::<Pos>().read::<Vel>().read_resource::<Gravity>());
+    assert!(info.is_parallel());
+    
+    let info = uses_rc_info();
+    assert_eq!(info.access().writes(), &[Pos::id(), Shared::id()]);
+    assert!(info.access().is_exclusive());
+    assert!(!info.is_parallel());
+    
+    let info = scatter_info();
+    assert_eq!(info.access(), &SystemAccess::new().write::<Pos>().read_resource::<Seed>().exclusive());
+    assert!(!info.is_parallel());
+    assert!(info.access().conflicts_with(&SystemAccess::new()));
+    
+    let info = print_info();
+    assert_eq!(info.access().reads(), &[Pos::id()]);
+    assert!(!info.is_parallel());
+    
+    assert!(!remove_far_info().is_parallel());
+    assert!(fall_info().access().conflicts_with(print_info().access()));
+}
+
+#[test]
+fn schedule_parallel() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+    
+    pos_comp!();
+    vel_comp!();
+    
+    #[derive(Component)]
+    struct Health(u32);
+    
+    /// The number of systems that are running
+    struct Running(AtomicUsize);
+    
+    // waits for the other system, so this only finishes in time if both systems
+    // run at the same time
+    fn wait_for_other(running: &Running) -> bool {
+        running.0.fetch_add(1, Ordering::SeqCst);
+        let start = Instant::now();
+        while running.0.load(Ordering::SeqCst) < 2 && start.elapsed() < Duration::from_secs(5) {
+            std::thread::yield_now();
+        }
+        running.0.load(Ordering::SeqCst) >= 2
+    }
+    
+    #[system(pos: &mut Pos, vel: &Vel, res running: Running)]
+    fn movement(world: &mut World) {
+        assert!(wait_for_other(running));
+        pos.x += vel.x;
+    }
+    
+    #[system(health: &mut Health, res running: Running)]
+    fn decay(world: &mut World) {
+        assert!(wait_for_other(running));
+        health.0 -= 1;
+    }
+    
+    #[system(pos: &mut Pos)]
+    fn wrap(world: &mut World) {
+        pos.x %= 10;
+    }
+    
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::Update, movement_info());
+    schedule.add_system(Stage::Update, decay_info());
+    // conflicts with movement, so it runs after both systems
+    schedule.add_system(Stage::Update, wrap_info());
+    
+    let mut world = World::new();
+    world.insert_resource(Running(AtomicUsize::new(0)));
+    let id1 = spawn_entity!(world, Pos { x: 8, y: 0 }, Vel { x: 3, y: 0 });
+    let id2 = spawn_entity!(world, Health(10));
+    
+    schedule.run_parallel(&mut world);
+    assert_eq!(world.get_component::<Pos>(id1), Some(&Pos { x: 1, y: 0 }));
+    assert_eq!(world.get_component::<Health>(id2).unwrap().0, 9);
+    
+    // ordering constraints prevent systems from running at the same time
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::Update, wrap_info());
+    schedule.add_system(Stage::Update, |world: &mut World| {
+        world.resource_mut::<Running>().unwrap().0.store(2, Ordering::SeqCst);
+    }).label("start");
+    schedule.add_system(Stage::Update, movement_info()).after("start");
+    schedule.add_system(Stage::Update, decay_info()).after("movement");
+    
+    schedule.run_parallel(&mut world);
+    assert_eq!(world.get_component::<Pos>(id1), Some(&Pos { x: 4, y: 0 }));
+    assert_eq!(world.get_component::<Health>(id2).unwrap().0, 8);
+}
+
+#[test]
+fn par_for_each() {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    
+    pos_comp!();
+    vel_comp!();
+    
+    #[derive(Component)]
+    #[allow(dead_code)]
+    struct Mass(u32);
+    
+    let mut world = World::new();
+    for i in 0..2000 {
+        if i % 2 == 0 {
+            spawn_entity!(world, Pos { x: i, y: 0 }, Vel { x: 1, y: 2 });
+        } else {
+            spawn_entity!(world, Pos { x: i, y: 0 }, Vel { x: 1, y: 2 }, Mass(1));
+        }
+    }
+    
+    query_mut!(world, Pos, Vel).par_for_each(|(pos, vel)| {
+        pos.x += vel.x;
+        pos.y += vel.y;
+    });
+    
+    let threads = Mutex::new(HashSet::new());
+    query_mut!(world, &mut Pos, &Vel).par_for_each_chunked(300, |(pos, vel)| {
+        threads.lock().unwrap().insert(std::thread::current().id());
+        pos.y += vel.y;
+    });
+    // 4 chunks per archetype, spread over at most the available threads
+    let available = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let threads = threads.into_inner().unwrap().len();
+    assert!(threads >= 1 && threads <= available.min(8));
+    
+    let mut xs: Vec<u32> = query!(world, Pos).map(|pos| pos.x).collect();
+    xs.sort();
+    assert_eq!(xs, (1..2001).collect::<Vec<u32>>());
+    assert!(query!(world, Pos).all(|pos| pos.y == 4));
+    
+    // items of shared queries can be used as well
+    let sum = std::sync::atomic::AtomicU32::new(0);
+    query!(world, Vel).par_for_each_chunked(100, |vel| {
+        sum.fetch_add(vel.x, std::sync::atomic::Ordering::Relaxed);
+    });
+    assert_eq!(sum.into_inner(), 2000);
+    
+    // the rows matching the filters are split as well
+    query_mut!(world, EntityId, Pos, with Mass).par_for_each_chunked(100, |(id, pos)| {
+        assert_eq!(id.index() % 2, 1);
+        pos.y = 0;
+    });
+    assert_eq!(query!(world, Pos).filter(|pos| pos.y == 0).count(), 1000);
+    
+    // the rest of a query that was already started
+    assert_eq!(query_mut!(world, Pos).size_hint(), (2000, Some(2000)));
+    let mut query = query_mut!(world, Pos);
+    query.next().unwrap().y = 1;
+    assert_eq!(query.size_hint(), (1999, Some(1999)));
+    query.par_for_each(|pos| pos.y = 1);
+    assert!(query!(world, Pos).all(|pos| pos.y == 1));
+}
+
+#[test]
+fn change_detection() {
+    pos_comp!();
+    vel_comp!();
+    
+    let mut world = World::new();
+    let id1 = spawn_entity!(world, Pos { x: 0, y: 0 });
+    let id2 = spawn_entity!(world, Pos { x: 0, y: 0 }, Vel { x: 1, y: 1 });
+    
+    let added: Vec<EntityId> = query!(world, EntityId, Pos, Added<Pos>).map(|(id, _)| id).collect();
+    assert_eq!(added, vec![id1, id2]);
+    
+    world.clear_trackers();
+    assert_eq!(query!(world, EntityId, Added<Pos>).count(), 0);
+    assert_eq!(query!(world, EntityId, Changed<Pos>).count(), 0);
+    
+    world.get_component_mut::<Pos>(id1).unwrap().x = 1;
+    world.set_component(id2, Vel { x: 2, y: 2 });
+    let changed: Vec<EntityId> = query!(world, EntityId, Changed<Pos>).collect();
+    assert_eq!(changed, vec![id1]);
+    let changed: Vec<EntityId> = query!(world, EntityId, or(Changed<Pos>, Changed<Vel>)).collect();
+    assert_eq!(changed, vec![id1, id2]);
+    // changes are no added components
+    assert_eq!(query!(world, EntityId, Added<Pos>).count(), 0);
+    
+    // only components that are written to are marked as changed
+    world.clear_trackers();
+    query_mut!(world, &Pos, &mut Vel).for_each(|(_, vel)| vel.x += 1);
+    assert_eq!(query!(world, EntityId, Changed<Pos>).count(), 0);
+    assert_eq!(query!(world, EntityId, Changed<Vel>).collect::<Vec<_>>(), vec![id2]);
+    
+    // ticks move with the component to the new archetype
+    world.clear_trackers();
+    world.insert_component(id1, Vel { x: 0, y: 0 });
+    assert_eq!(query!(world, EntityId, Added<Pos>).count(), 0);
+    assert_eq!(query!(world, EntityId, Added<Vel>).collect::<Vec<_>>(), vec![id1]);
+    assert_eq!(query_mut!(world, EntityId, Pos, Changed<Vel>).map(|(id, _)| id).collect::<Vec<_>>(), vec![id1]);
+}
+
+#[test]
+fn system_change_detection() {
+    pos_comp!();
+    vel_comp!();
+    
+    struct Log(Vec<EntityId>);
+    
+    #[system(pos: &mut Pos, vel: &Vel)]
+    fn movement(world: &mut World) {
+        pos.x += vel.x;
+    }
+    
+    #[system(id: EntityId, pos: &Pos, Changed<Pos>, res mut log: Log)]
+    fn log_changed(world: &mut World) {
+        let _ = pos;
+        log.0.push(id);
+    }
+    
+    let info = log_changed_info();
+    assert_eq!(info.access().reads(), &[Pos::id()]);
+    
+    for parallel in [false, true] {
+        let mut world = World::new();
+        world.insert_resource(Log(Vec::new()));
+        let id1 = spawn_entity!(world, Pos { x: 0, y: 0 });
+        let id2 = spawn_entity!(world, Pos { x: 0, y: 0 }, Vel { x: 1, y: 1 });
+        
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, log_changed_info());
+        schedule.add_system(Stage::PreUpdate, movement_info());
+        let mut run = |world: &mut World| if parallel {
+            schedule.run_parallel(world);
+        } else {
+            schedule.run(world);
+        };
+        
+        // the first run reports all components
+        run(&mut world);
+        assert_eq!(std::mem::take(&mut world.resource_mut::<Log>().unwrap().0), vec![id1, id2]);
+        
+        // changes since the last run of the system
+        run(&mut world);
+        assert_eq!(std::mem::take(&mut world.resource_mut::<Log>().unwrap().0), vec![id2]);
+        world.get_component_mut::<Pos>(id1).unwrap().y = 1;
+        run(&mut world);
+        assert_eq!(std::mem::take(&mut world.resource_mut::<Log>().unwrap().0), vec![id1, id2]);
+    }
+}
+
+#[test]
+fn removal_tracking() {
+    pos_comp!();
+    vel_comp!();
+    
+    let mut world = World::new();
+    let id1 = spawn_entity!(world, Pos { x: 0, y: 0 }, Vel { x: 0, y: 0 });
+    let id2 = spawn_entity!(world, Pos { x: 0, y: 0 }, Vel { x: 0, y: 0 });
+    let id3 = spawn_entity!(world, Pos { x: 0, y: 0 });
+    
+    world.kill(id1);
+    world.remove_component::<Vel>(id2);
+    // not alive, nothing is removed
+    world.kill(id1);
+    assert!(world.remove_component::<Vel>(id3).is_none());
+    
+    assert_eq!(world.removed::<Vel>().collect::<Vec<_>>(), vec![id1, id2]);
+    assert_eq!(world.removed::<Pos>().collect::<Vec<_>>(), vec![id1]);
+    assert_eq!(world.despawned().collect::<Vec<_>>(), vec![id1]);
+    
+    world.clear_trackers();
+    assert_eq!(world.removed::<Vel>().count(), 0);
+    assert_eq!(world.despawned().count(), 0);
+    
+    let mut commands = world.commands();
+    commands.kill(id3);
+    world.apply_commands(&mut commands);
+    assert_eq!(world.removed::<Pos>().collect::<Vec<_>>(), vec![id3]);
+    assert_eq!(world.despawned().collect::<Vec<_>>(), vec![id3]);
+}
+
+#[test]
+fn system_removal_tracking() {
+    pos_comp!();
+    
+    struct Freed(Vec<EntityId>);
+    struct ToKill(Vec<EntityId>);
+    
+    fn kill(world: &mut World) {
+        for id in std::mem::take(&mut world.resource_mut::<ToKill>().unwrap().0) {
+            world.kill(id);
+        }
+    }
+    
+    fn free_bodies(world: &mut World) {
+        let removed: Vec<EntityId> = world.removed::<Pos>().collect();
+        world.resource_mut::<Freed>().unwrap().0.extend(removed);
+    }
+    
+    let mut world = World::new();
+    world.insert_resource(Freed(Vec::new()));
+    let id1 = spawn_entity!(world, Pos { x: 0, y: 0 });
+    let id2 = spawn_entity!(world, Pos { x: 0, y: 0 });
+    world.insert_resource(ToKill(vec![id1]));
+    
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::PreUpdate, free_bodies);
+    schedule.add_system(Stage::Update, kill);
+    
+    // free_bodies runs before kill, so it sees the removal in the next run
+    schedule.run(&mut world);
+    assert!(world.resource::<Freed>().unwrap().0.is_empty());
+    world.resource_mut::<ToKill>().unwrap().0.push(id2);
+    schedule.run(&mut world);
+    assert_eq!(world.resource::<Freed>().unwrap().0, vec![id1]);
+    schedule.run(&mut world);
+    assert_eq!(world.resource::<Freed>().unwrap().0, vec![id1, id2]);
+    schedule.run(&mut world);
+    assert_eq!(world.resource::<Freed>().unwrap().0, vec![id1, id2]);
+    
+    // all systems have seen the removals, so they are forgotten
+    assert_eq!(world.removed::<Pos>().count(), 0);
+}
+
+#[test]
+fn schedule_forgets_removals() {
+    pos_comp!();
+    
+    fn respawn(world: &mut World) {
+        let ids: Vec<EntityId> = query!(world, EntityId, Pos).map(|(id, _)| id).collect();
+        for id in ids {
+            world.kill(id);
+        }
+        spawn_entity!(world, Pos { x: 0, y: 0 });
+    }
+    
+    fn count_removed(world: &mut World) {
+        let removed = world.removed::<Pos>().count();
+        assert!(removed <= 1);
+    }
+    
+    fn never_runs(_: &mut World) {
+        unreachable!();
+    }
+    
+    let mut world = World::new();
+    spawn_entity!(world, Pos { x: 0, y: 0 });
+    
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::Update, respawn);
+    schedule.add_system(Stage::PostUpdate, count_removed);
+    // a system that never runs doesn't keep the removals in the log
+    schedule.add_system(Stage::PostUpdate, never_runs).run_if(|_| false);
+    for _ in 0..100 {
+        schedule.run(&mut world);
+        // the log only contains the removals a system hasn't seen yet
+        assert!(world.despawned().count() <= 1);
+    }
+    for _ in 0..100 {
+        schedule.run_parallel(&mut world);
+        assert!(world.despawned().count() <= 1);
+    }
+}
+
+#[test]
+fn events() {
+    #[derive(Debug, PartialEq)]
+    struct Collision(u32);
+    
+    let mut world = World::new();
+    let mut cursor = EventCursor::<Collision>::new();
+    assert_eq!(world.read_events::<Collision>().len(), 0);
+    assert_eq!(cursor.read(&world).count(), 0);
+    
+    world.send_event(Collision(0));
+    world.send_event(Collision(1));
+    assert_eq!(world.read_events::<Collision>().iter().collect::<Vec<_>>(), vec![&Collision(0), &Collision(1)]);
+    assert_eq!(cursor.read(&world).collect::<Vec<_>>(), vec![&Collision(0), &Collision(1)]);
+    assert_eq!(cursor.read(&world).count(), 0);
+    
+    // events are kept for two updates
+    world.update_events();
+    world.send_event(Collision(2));
+    assert_eq!(world.resource::<Events<Collision>>().unwrap().len(), 3);
+    assert_eq!(cursor.read(&world).collect::<Vec<_>>(), vec![&Collision(2)]);
+    world.update_events();
+    assert_eq!(world.resource::<Events<Collision>>().unwrap().iter().collect::<Vec<_>>(), vec![&Collision(2)]);
+    
+    // only events sent after clearing the trackers are read
+    world.clear_trackers();
+    assert!(world.read_events::<Collision>().is_empty());
+    world.send_event(Collision(3));
+    assert_eq!(world.read_events::<Collision>().into_iter().collect::<Vec<_>>(), vec![&Collision(3)]);
+    
+    // a new cursor reads all events in the queue
+    let mut other_cursor = EventCursor::<Collision>::new();
+    assert_eq!(other_cursor.read(&world).count(), 2);
+    assert_eq!(cursor.read(&world).count(), 1);
+}
+
+#[test]
+fn events_without_schedule() {
+    struct Tick(u32);
+    
+    // a world driven by hand only clears its trackers at the end of every frame
+    let mut world = World::new();
+    for frame in 0..10 {
+        world.send_event(Tick(frame));
+        assert_eq!(world.read_events::<Tick>().iter().map(|tick| tick.0).collect::<Vec<_>>(), vec![frame]);
+        world.clear_trackers();
+        // only the events of this frame are kept
+        assert_eq!(world.resource::<Events<Tick>>().unwrap().len(), 1);
+    }
+    
+    // clearing the trackers right after a schedule ran doesn't update the events again
+    let mut schedule = Schedule::new();
+    schedule.add_system(Stage::Update, |world: &mut World| world.send_event(Tick(10)));
+    schedule.run(&mut world);
+    world.clear_trackers();
+    assert_eq!(world.resource::<Events<Tick>>().unwrap().iter().map(|tick| tick.0).collect::<Vec<_>>(), vec![10]);
+    world.clear_trackers();
+    assert!(world.resource::<Events<Tick>>().unwrap().is_empty());
+}
+
+#[test]
+fn system_events() {
+    pos_comp!();
+    
+    #[derive(Debug, PartialEq)]
+    struct Collision(EntityId);
+    struct Log(Vec<EntityId>);
+    
+    #[system(id: EntityId, pos: &Pos, events mut collisions: Collision)]
+    fn collide(world: &mut World) {
+        if pos.x == 0 {
+            collisions.send(Collision(id));
+        }
+    }
+    
+    #[system(pos: &Pos, events collisions: Collision, res mut log: Log)]
+    fn log_collisions(world: &mut World) {
+        if pos.y == 1 {
+            log.0.extend(collisions.iter().map(|collision| collision.0));
+        }
+    }
+    
+    #[system(pos: &mut Pos)]
+    fn move_away(world: &mut World) {
+        pos.x += 1;
+    }
+    
+    assert!(collide_info().is_parallel());
+    assert!(collide_info().access().conflicts_with(log_collisions_info().access()));
+    
+    for parallel in [false, true] {
+        let mut world = World::new();
+        world.add_event::<Collision>();
+        world.insert_resource(Log(Vec::new()));
+        let id1 = spawn_entity!(world, Pos { x: 0, y: 0 });
+        let id2 = spawn_entity!(world, Pos { x: 0, y: 1 });
+        
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::PreUpdate, log_collisions_info());
+        schedule.add_system(Stage::Update, collide_info());
+        schedule.add_system(Stage::PostUpdate, move_away_info());
+        
+        let mut run = |world: &mut World| if parallel {
+            schedule.run_parallel(world);
+        } else {
+            schedule.run(world);
+        };
+        
+        // log_collisions runs before collide, so it reads the events in the next run
+        run(&mut world);
+        assert!(world.resource::<Log>().unwrap().0.is_empty());
+        run(&mut world);
+        assert_eq!(world.resource::<Log>().unwrap().0, vec![id1, id2]);
+        run(&mut world);
+        assert_eq!(world.resource::<Log>().unwrap().0, vec![id1, id2]);
+        assert!(world.resource::<Events<Collision>>().unwrap().is_empty());
+    }
+}
+
+#[test]
+#[should_panic(expected = "was not added to the world")]
+fn send_missing_event() {
+    pos_comp!();
+    
+    #[system(pos: &Pos, events mut collisions: u32)]
+    fn collide(world: &mut World) {
+        collisions.send(pos.x);
+    }
+    
+    let mut world = World::new();
+    spawn_entity!(world, Pos { x: 0, y: 0 });
+    collide(&mut world);
+}
+
+#[test]
+fn save_and_load() {
+    #[derive(Debug, PartialEq, Component)]
+    #[component(serialize)]
+    struct Pos {
+        x: u32, y: u32
+    }
+    
+    #[derive(Debug, PartialEq, Component)]
+    #[component(serialize, name = "save_and_load::Name")]
+    struct Name(String);
+    
+    #[derive(Debug, PartialEq, Component)]
+    #[component(serialize)]
+    struct Parent(Option<EntityId>);
+    
+    #[derive(Debug, PartialEq, Serializable)]
+    enum Shape {
+        Circle { radius: f32 },
+        Rect(f32, f32),
+        Point,
+    }
+    
+    #[derive(Debug, PartialEq, Component)]
+    #[component(serialize)]
+    struct Shapes(Vec<Shape>);
+    
+    // not saved
+    #[derive(Debug, PartialEq, Component)]
+    struct Texture(u32);
+    
+    let mut world = World::new();
+    let killed = spawn_entity!(world, Pos { x: 0, y: 0 });
+    let root = spawn_entity!(world, Pos { x: 1, y: 2 }, Name("root".to_string()), Texture(1));
+    let child = spawn_entity!(world, Parent(Some(root)), Shapes(vec![Shape::Circle { radius: 1.0 }, Shape::Rect(1.0, 2.0), Shape::Point]));
+    let textured = spawn_entity!(world, Texture(2));
+    world.kill(killed);
+    world.set_flag(child, Flags::Flag2);
+    
+    let mut bytes = Vec::new();
+    world.save(&mut bytes).unwrap();
+    // components are saved under their module path and type name by default
+    let saved_as = |name: &str| bytes.windows(name.len()).any(|window| window == name.as_bytes());
+    assert!(saved_as("tests::Pos") && saved_as("tests::Parent") && saved_as("save_and_load::Name"));
+    let mut loaded = World::load(&mut bytes.as_slice()).unwrap();
+    
+    assert_eq!(loaded.entity_count(), 3);
+    assert!(!loaded.is_alive(killed));
+    assert_eq!(loaded.get_component::<Pos>(root), Some(&Pos { x: 1, y: 2 }));
+    assert_eq!(loaded.get_component::<Name>(root), Some(&Name("root".to_string())));
+    assert!(!loaded.has_component::<Texture>(root));
+    assert_eq!(loaded.get_component::<Parent>(child), Some(&Parent(Some(root))));
+    assert_eq!(loaded.get_component::<Shapes>(child).unwrap().0[1], Shape::Rect(1.0, 2.0));
+    assert!(loaded.has_flag(child, Flags::Flag2));
+    assert!(!loaded.has_flag(child, Flags::Flag1));
+    // entities without serializable components are kept
+    assert!(loaded.is_alive(textured));
+    assert_eq!(query!(loaded, Pos).count(), 1);
+    
+    // the index of the killed entity is reused with a new generation
+    let new = spawn_entity!(loaded, Pos { x: 3, y: 3 });
+    assert_eq!(new.index(), killed.index());
+    assert!(!loaded.is_alive(killed));
+    assert!(loaded.is_alive(new));
+    
+    // saving the loaded world gives the same world
+    let mut bytes2 = Vec::new();
+    World::load(&mut bytes.as_slice()).unwrap().save(&mut bytes2).unwrap();
+    assert_eq!(bytes, bytes2);
+}
+
+#[test]
+fn load_component_order() {
+    #[derive(Debug, PartialEq, Component)]
+    #[component(serialize, name = "load_component_order::B")]
+    struct Small(u8);
+    
+    #[derive(Debug, PartialEq, Component)]
+    #[component(serialize, name = "load_component_order::A")]
+    struct Large(u64, String);
+    
+    // the component with the first name is registered last, so its id is larger,
+    // like when another process registered the components in a different order
+    assert!(Small::id() < Large::id());
+    
+    let mut world = World::new();
+    let id = spawn_entity!(world, Small(7), Large(u64::MAX, "large".to_string()));
+    let mut bytes = Vec::new();
+    world.save(&mut bytes).unwrap();
+    
+    // the components are stored in the order of their names
+    let large_start = bytes.windows(5).position(|bytes| bytes == b"large").unwrap();
+    let small_start = bytes.len() - 1;
+    assert_eq!(bytes[small_start], 7);
+    assert!(large_start < small_start);
+    
+    let loaded = World::load(&mut bytes.as_slice()).unwrap();
+    assert_eq!(loaded.get_component::<Small>(id), Some(&Small(7)));
+    assert_eq!(loaded.get_component::<Large>(id), Some(&Large(u64::MAX, "large".to_string())));
+}
+
+#[test]
+fn register_before_load() {
+    #[derive(Debug, PartialEq, Component)]
+    #[component(serialize, name = "register_before_load::Saved")]
+    struct Saved(u32);
+    
+    // not used before the world is loaded
+    #[derive(Debug, PartialEq, Component)]
+    #[component(serialize, name = "register_before_load::Fresh")]
+    struct Fresh(u32);
+    
+    let mut world = World::new();
+    let id = spawn_entity!(world, Saved(5));
+    let mut bytes = Vec::new();
+    world.save(&mut bytes).unwrap();
+    // saved by a program in which the component is called `Fresh`
+    let name_start = bytes.windows(5).position(|bytes| bytes == b"Saved").unwrap();
+    bytes[name_start..name_start + 5].copy_from_slice(b"Fresh");
+    
+    assert!(matches!(World::load(&mut bytes.as_slice()), Err(SerializeError::UnknownComponent(_))));
+    World::register_serializable::<Fresh>();
+    let loaded = World::load(&mut bytes.as_slice()).unwrap();
+    assert_eq!(loaded.get_component::<Fresh>(id), Some(&Fresh(5)));
+}
+
+#[test]
+fn load_invalid_world() {
+    #[derive(Debug, PartialEq, Component)]
+    #[component(serialize, name = "load_invalid_world::Pos")]
+    struct Pos {
+        x: u32, y: u32
+    }
+    
+    let mut world = World::new();
+    spawn_entity!(world, Pos { x: 0, y: 0 });
+    let mut bytes = Vec::new();
+    world.save(&mut bytes).unwrap();
+    
+    assert!(matches!(World::load(&mut &b"not a world"[..]), Err(SerializeError::InvalidFormat)));
+    for len in 0..bytes.len() {
+        assert!(World::load(&mut &bytes[..len]).is_err());
+    }
+    
+    let name_start = bytes.windows(3).position(|bytes| bytes == b"Pos").unwrap();
+    bytes[name_start] = b'X';
+    match World::load(&mut bytes.as_slice()) {
+        Err(SerializeError::UnknownComponent(name)) => assert_eq!(name, "load_invalid_world::Xos"),
+        _ => panic!("expected an unknown component"),
+    }
+}
+
+#[test]
+fn snapshot_and_restore() {
+    #[derive(Debug, PartialEq, Clone, Component)]
+    #[component(clone)]
+    struct Pos {
+        x: u32, y: u32
+    }
+    
+    #[derive(Debug, PartialEq, Clone, Component)]
+    #[component(clone, serialize)]
+    struct Name(String);
+    
+    #[derive(Debug, PartialEq, Clone, Component)]
+    #[component(clone)]
+    struct Marker {}
+    
+    #[derive(Debug, PartialEq, Clone)]
+    struct Score(u32);
+    struct Assets(u32);
+    
+    let mut world = World::new();
+    world.register_cloneable_resource::<Score>();
+    world.insert_resource(Score(1));
+    world.insert_resource(Assets(1));
+    let id1 = spawn_entity!(world, Pos { x: 0, y: 0 }, Name("one".to_string()));
+    let id2 = spawn_entity!(world, Pos { x: 1, y: 1 }, Marker {});
+    world.set_flag(id2, Flags::Flag1);
+    
+    let snapshot = world.snapshot();
+    
+    world.get_component_mut::<Pos>(id1).unwrap().x = 5;
+    world.get_component_mut::<Name>(id1).unwrap().0.push_str(" changed");
+    world.kill(id2);
+    let id3 = spawn_entity!(world, Pos { x: 3, y: 3 });
+    world.insert_component(id1, Marker {});
+    world.resource_mut::<Score>().unwrap().0 = 2;
+    world.resource_mut::<Assets>().unwrap().0 = 2;
+    let mut commands = world.commands();
+    let reserved = commands.spawn();
+    
+    world.restore(&snapshot);
+    assert_eq!(world.entity_count(), 2);
+    assert_eq!(world.get_component::<Pos>(id1), Some(&Pos { x: 0, y: 0 }));
+    assert_eq!(world.get_component::<Name>(id1), Some(&Name("one".to_string())));
+    assert!(!world.has_component::<Marker>(id1));
+    assert!(world.is_alive(id2));
+    assert!(world.has_component::<Marker>(id2));
+    assert!(world.has_flag(id2, Flags::Flag1));
+    assert!(!world.is_alive(id3));
+    assert_eq!(world.resource::<Score>(), Some(&Score(1)));
+    assert_eq!(world.resource::<Assets>().unwrap().0, 2);
+    assert_eq!(query!(world, Pos).count(), 2);
+    
+    // ids reserved before restoring are handed out again, so the commands for them are skipped
+    let id4 = spawn_entity!(world, Name("four".to_string()));
+    assert_eq!(id4, reserved);
+    commands.insert(reserved, Pos { x: 4, y: 4 });
+    world.apply_commands(&mut commands);
+    assert!(!world.has_component::<Pos>(id4));
+    assert_eq!(world.entity_count(), 3);
+    // commands created after restoring are applied
+    let mut commands = world.commands();
+    let id6 = commands.spawn();
+    world.apply_commands(&mut commands);
+    assert!(world.is_alive(id6));
+    world.kill(id6);
+    
+    // the snapshot can be restored multiple times
+    world.restore(&snapshot);
+    assert_eq!(world.entity_count(), 2);
+    
+    // clones are independent of the original world
+    let mut clone = world.clone();
+    clone.get_component_mut::<Pos>(id1).unwrap().x = 10;
+    clone.kill(id2);
+    assert_eq!(world.get_component::<Pos>(id1), Some(&Pos { x: 0, y: 0 }));
+    assert!(world.is_alive(id2));
+    assert_eq!(clone.resource::<Score>(), Some(&Score(1)));
+    assert!(!clone.has_resource::<Assets>());
+}
+
+#[test]
+fn restore_tracking() {
+    #[derive(Debug, PartialEq, Clone, Component)]
+    #[component(clone)]
+    struct Pos {
+        x: u32, y: u32
+    }
+    
+    let mut world = World::new();
+    let id1 = spawn_entity!(world, Pos { x: 0, y: 0 });
+    let id2 = spawn_entity!(world, Pos { x: 1, y: 1 });
+    world.kill(id1);
+    world.clear_trackers();
+    let id4 = spawn_entity!(world, Pos { x: 4, y: 4 });
+    let snapshot = world.snapshot();
+    world.clear_trackers();
+    
+    let id3 = spawn_entity!(world, Pos { x: 3, y: 3 });
+    world.kill(id2);
+    assert_eq!(query!(world, EntityId, Pos, Changed<Pos>).map(|(id, _)| id).collect::<Vec<_>>(), vec![id3]);
+    world.restore(&snapshot);
+    
+    // the entities spawned after the snapshot get the same ids again
+    assert!(!world.is_alive(id3));
+    assert_eq!(spawn_entity!(world, Pos { x: 3, y: 3 }), id3);
+    world.kill(id3);
+    
+    // the restored ticks are as recent as when the snapshot was taken, so only
+    // the component added in the frame of the snapshot is reported as changed
+    assert_eq!(query!(world, EntityId, Pos, Changed<Pos>).map(|(id, _)| id).collect::<Vec<_>>(), vec![id4]);
+    assert_eq!(query!(world, EntityId, Pos, Added<Pos>).map(|(id, _)| id).collect::<Vec<_>>(), vec![id4]);
+    // the removals made after the snapshot are kept
+    assert_eq!(world.despawned().collect::<Vec<_>>(), vec![id2, id3]);
+    world.clear_trackers();
+    assert_eq!(world.despawned().count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "can't be cloned, add `#[component(clone)]`")]
+fn snapshot_not_cloneable() {
+    pos_comp!();
+    
+    let mut world = World::new();
+    spawn_entity!(world, Pos { x: 0, y: 0 });
+    world.snapshot();
+}
+
+#[test]
+fn dense_archetype_rows() {
+    use std::rc::Rc;
+    
+    pos_comp!();
+    vel_comp!();
+    
+    // the handle is only dropped, never read
+    #[derive(Component)]
+    #[allow(dead_code)]
+    struct Handle(Rc<()>);
+    
+    let rc = Rc::new(());
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..6)
+        .map(|i| spawn_entity!(world, Pos { x: i, y: i }, Handle(rc.clone())))
+        .collect();
+    
+    // the last entity is moved into the row of the first one
+    world.kill(ids[0]);
+    world.remove_component::<Handle>(ids[2]);
+    world.insert_component(ids[3], Vel { x: 3, y: 3 });
+    world.kill(ids[5]);
+    assert_eq!(Rc::strong_count(&rc), 4);
+    
+    for (i, id) in ids.iter().enumerate() {
+        let alive = i != 0 && i != 5;
+        assert_eq!(world.is_alive(*id), alive);
+        if alive {
+            assert_eq!(world.get_component::<Pos>(*id), Some(&Pos { x: i as u32, y: i as u32 }));
+            assert_eq!(world.has_component::<Handle>(*id), i != 2);
+        }
+    }
+    let mut positions: Vec<(EntityId, &Pos)> = query!(world, EntityId, Pos).collect();
+    positions.sort_by_key(|(_, pos)| pos.x);
+    assert_eq!(positions.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![ids[1], ids[2], ids[3], ids[4]]);
+    assert!(positions.iter().all(|(id, pos)| ids[pos.x as usize] == *id));
+    
+    // moving entities between archetypes keeps the rows of the other entities valid
+    for id in &ids[1..5] {
+        world.remove_component::<Pos>(*id);
+    }
+    assert_eq!(query!(world, Pos).count(), 0);
+    assert_eq!(query!(world, Handle).count(), 3);
+    assert_eq!(world.get_component::<Vel>(ids[3]), Some(&Vel { x: 3, y: 3 }));
+    
+    drop(world);
+    assert_eq!(Rc::strong_count(&rc), 1);
+}
+
+#[test]
+fn query_chunks() {
+    pos_comp!();
+    vel_comp!();
+    
+    #[derive(Component)]
+    struct Marker {}
+    
+    let mut world = World::new();
+    let mut ids = Vec::new();
+    for i in 0..3 {
+        ids.push(spawn_entity!(world, Pos { x: i, y: 0 }, Vel { x: 1, y: 2 }));
+    }
+    ids.push(spawn_entity!(world, Pos { x: 3, y: 0 }, Vel { x: 1, y: 2 }, Marker {}));
+    let no_vel = spawn_entity!(world, Pos { x: 4, y: 0 });
+    world.clear_trackers();
+    
+    // one chunk per archetype, with the rows of the slices matching
+    for (entities, pos, vel) in world.query_chunks_mut::<(Pos, Read<Vel>)>() {
+        assert_eq!(entities.len(), pos.len());
+        assert_eq!(entities.len(), vel.len());
+        for (pos, vel) in pos.iter_mut().zip(vel) {
+            pos.x += vel.x;
+            pos.y += vel.y;
+        }
+    }
+    assert_eq!(world.query_chunks::<(Pos, Vel)>().count(), 2);
+    for (i, id) in ids.iter().enumerate() {
+        assert_eq!(world.get_component::<Pos>(*id), Some(&Pos { x: i as u32 + 1, y: 2 }));
+    }
+    assert_eq!(world.get_component::<Pos>(no_vel), Some(&Pos { x: 4, y: 0 }));
+    
+    // all components in a mutable slice are marked as changed
+    let mut changed: Vec<EntityId> = query!(world, EntityId, Changed<Pos>).collect();
+    changed.sort();
+    assert_eq!(changed, ids);
+    assert_eq!(query!(world, EntityId, Changed<Vel>).count(), 0);
+    
+    let mut entities: Vec<EntityId> = Vec::new();
+    let mut markers = 0;
+    for (chunk_entities, pos, marker) in world.query_chunks::<(Pos, Option<Marker>)>() {
+        assert_eq!(chunk_entities.len(), pos.len());
+        entities.extend_from_slice(chunk_entities);
+        markers += marker.map(|marker| marker.len()).unwrap_or(0);
+    }
+    entities.sort();
+    assert_eq!(entities, vec![ids[0], ids[1], ids[2], ids[3], no_vel]);
+    assert_eq!(markers, 1);
+    
+    // empty archetypes are skipped
+    world.kill(ids[3]);
+    assert_eq!(world.query_chunks::<(Marker,)>().count(), 0);
+}
+
+#[test]
+fn entity_counts() {
+    pos_comp!();
+    vel_comp!();
+    
+    let mut world = World::new();
+    let ids: Vec<EntityId> = (0..10)
+        .map(|i| spawn_entity!(world, Pos { x: i, y: 0 }))
+        .collect();
+    let moving = spawn_entity!(world, Pos { x: 0, y: 0 }, Vel { x: 1, y: 1 });
+    assert_eq!(world.entity_count(), 11);
+    
+    for id in &ids[..8] {
+        world.kill(*id);
+    }
+    world.kill(ids[0]);
+    assert_eq!(world.entity_count(), 3);
+    assert_eq!(world.query_ids().collect::<Vec<_>>(), vec![ids[8], ids[9], moving]);
+    
+    let arch = world.archetype_of(ids[8]).unwrap();
+    assert_eq!(world.archetype_len(arch), 2);
+    assert_ne!(world.archetype_of(moving), Some(arch));
+    assert_eq!(world.archetype_of(ids[0]), None);
+    assert_eq!(world.query_count::<(Pos,)>(), 3);
+    assert_eq!(world.query_count::<(Pos, Vel)>(), 1);
+    assert_eq!(world.query_count::<(Pos, Option<Vel>)>(), 3);
+    
+    // reserved ids are counted once spawned
+    let mut commands = world.commands();
+    let spawned = commands.spawn();
+    commands.insert(spawned, Vel { x: 0, y: 0 });
+    let _ = spawn_entity!(world, Pos { x: 0, y: 0 });
+    assert_eq!(world.entity_count(), 4);
+    world.apply_commands(&mut commands);
+    world.insert_component(ids[9], Vel { x: 0, y: 0 });
+    assert_eq!(world.entity_count(), 5);
+    assert_eq!(world.query_count::<(Vel,)>(), 3);
+    assert_eq!(world.archetype_len(arch), 2);
+    assert_eq!(world.query_ids().count(), 5);
+}
+
+#[test]
+#[allow(deprecated)]
+fn entity_id_reuse() {
+    pos_comp!();
+    
+    let mut world = World::new();
+    let mut alive: Vec<EntityId> = Vec::new();
+    let mut killed: Vec<EntityId> = Vec::new();
+    // xorshift, so that the interleavings are reproducible
+    let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+    let mut random = |n: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % n as u64) as usize
+    };
+    
+    for i in 0..2000 {
+        match random(6) {
+            0..=2 => {
+                let id = spawn_entity!(world, Pos { x: i, y: 0 });
+                assert!(world.is_alive(id));
+                alive.push(id);
+            }
+            3 if !alive.is_empty() => {
+                let id = alive.swap_remove(random(alive.len()));
+                world.kill(id);
+                killed.push(id);
+            }
+            4 if !alive.is_empty() => {
+                // killing and freeing multiple times doesn't free the index twice
+                let id = alive.swap_remove(random(alive.len()));
+                world.kill_and_keep(id);
+                world.free_id(id);
+                world.free_id(id);
+                world.kill(id);
+                killed.push(id);
+            }
+            5 if !killed.is_empty() => {
+                // stale ids are ignored
+                world.kill(killed[random(killed.len())]);
+            }
+            _ => {}
+        }
+    }
+    
+    assert_eq!(world.entity_count(), alive.len());
+    assert_eq!(query!(world, Pos).count(), alive.len());
+    assert!(alive.iter().all(|id| world.is_alive(*id)));
+    assert!(killed.iter().all(|id| !world.is_alive(*id)));
+    
+    let mut ids: Vec<EntityId> = world.query_ids().collect();
+    ids.dedup_by_key(|id| id.index());
+    assert_eq!(ids.len(), alive.len());
+}
+
 mod example {
     use super::*;
     
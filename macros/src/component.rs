@@ -1,16 +1,50 @@
 use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
-use std::sync::Mutex;
 
-static mut COMP_ID_COUNTER: Mutex<u32> = Mutex::new(0);
+/// The options of `#[component(...)]`
+pub struct ComponentAttrs {
+    /// `#[component(serialize)]`
+    pub serialize: bool,
+    /// `#[component(clone)]`
+    pub clone: bool,
+    /// `#[component(serialize, name = "...")]`, defaults to the module path and
+    /// name of the type
+    pub name: Option<String>,
+}
 
-/// `field_less`: whether the strut has no fields
-pub fn derive_component_impl(name: &proc_macro2::Ident, generics_and_lifetimes: &syn::Generics) -> TokenStream2 {
-    let comp_id = unsafe { &COMP_ID_COUNTER };
-    let mut guard = comp_id.lock().unwrap();
-    let this_id = *guard;
-    *guard += 1;
-    
+pub fn parse_component_attrs(ast: &syn::DeriveInput) -> ComponentAttrs {
+    let mut attrs = ComponentAttrs { serialize: false, clone: false, name: None };
+    for attr in ast.attrs.iter().filter(|attr| attr.path.is_ident("component")) {
+        let nested = match attr.parse_meta() {
+            Ok(syn::Meta::List(list)) => list.nested,
+            _ => panic!("Expected `#[component(...)]`"),
+        };
+        for meta in nested {
+            match meta {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("serialize") => {
+                    attrs.serialize = true;
+                },
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("clone") => {
+                    attrs.clone = true;
+                },
+                syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue { path, lit: syn::Lit::Str(name), .. })) if path.is_ident("name") => {
+                    attrs.name = Some(name.value());
+                },
+                meta => panic!("Unknown component option `{}`, expected `serialize`, `clone` or `name = \"...\"`", quote! { #meta }),
+            }
+        }
+    }
+    if attrs.name.is_some() && !attrs.serialize {
+        panic!("The name of a component is only used when it is serialized, add `#[component(serialize)]`");
+    }
+    if attrs.serialize && !ast.generics.params.is_empty() {
+        panic!("Generic components can't be serialized, because every instance of the type needs its own stable name. Use a non-generic component instead");
+    }
+    return attrs;
+}
+
+pub fn derive_component_impl(name: &proc_macro2::Ident, generics_and_lifetimes: &syn::Generics, attrs: &ComponentAttrs) -> TokenStream2 {
+    let ident = name;
     let name = quote! {
         #name
     };
@@ -44,41 +78,167 @@ pub fn derive_component_impl(name: &proc_macro2::Ident, generics_and_lifetimes:
         }).collect::<Vec<TokenStream2>>();
     
     let generics_def = generics_and_lifetimes;
+    let where_clause = &generics_and_lifetimes.where_clause;
+    let static_bound = if where_clause.is_some() {
+        quote! { , #name<#(#generics_code)*>: 'static }
+    } else {
+        quote! { where #name<#(#generics_code)*>: 'static }
+    };
+    
+    // Serializable and cloneable components register their functions when their
+    // id is assigned, once per type
+    let mut registrations = Vec::new();
+    if attrs.serialize {
+        let save_name = match &attrs.name {
+            Some(save_name) => quote! { #save_name },
+            // unlike `type_name`, this doesn't change between compiler versions
+            None => quote! { concat!(module_path!(), "::", stringify!(#ident)) },
+        };
+        registrations.push(quote! { kiwi_ecs::register_serializable::<Self>(id, #save_name); });
+    }
+    if attrs.clone {
+        registrations.push(quote! { kiwi_ecs::register_clone::<Self>(id); });
+    }
+    let get_id = if registrations.is_empty() {
+        quote! { kiwi_ecs::component_id::<Self>() }
+    } else {
+        quote! {
+            kiwi_ecs::component_id_with::<Self>(|id| {
+                #(#registrations)*
+            })
+        }
+    };
+    
+    // Ids are assigned at runtime, so they are the same for components defined
+    // in different crates. Non-generic components cache their id.
+    let id_body = if generics_and_lifetimes.params.is_empty() {
+        quote! {
+            static ID: ::std::sync::OnceLock<kiwi_ecs::ComponentId> = ::std::sync::OnceLock::new();
+            *ID.get_or_init(|| #get_id)
+        }
+    } else {
+        quote! {
+            #get_id
+        }
+    };
     
     quote! {
-        impl #generics_def #name<#(#generics_code)*> {
-            #[inline(always)]
-            fn get_archetypes_rwlock() -> &'static std::sync::RwLock<Vec<kiwi_ecs::ArchetypeId>> {
-                static ARCHETYPES: std::sync::RwLock<Vec<kiwi_ecs::ArchetypeId>> = std::sync::RwLock::new(Vec::new());
-                &ARCHETYPES
-            }
-            
-            #[inline(always)]
-            fn get_archetypes_read() -> std::sync::RwLockReadGuard<'static, Vec<kiwi_ecs::ArchetypeId>> {
-                let archetype = Self::get_archetypes_rwlock();
-                let guard = archetype.read().unwrap();
-                guard
-            }
-            
-            #[inline(always)]
-            fn get_archetypes_write() -> std::sync::RwLockWriteGuard<'static, Vec<kiwi_ecs::ArchetypeId>> {
-                let archetype = Self::get_archetypes_rwlock();
-                let guard = archetype.write().unwrap();
-                guard
+        impl #generics_def Component for #name<#(#generics_code)*> #where_clause #static_bound {
+            #[inline]
+            fn id() -> kiwi_ecs::ComponentId where Self: Sized {
+                #id_body
             }
         }
-        impl #generics_def Component for #name<#(#generics_code)*> {
-            #[inline]
-            fn get_archetypes() -> ::std::sync::RwLockReadGuard<'static, Vec<kiwi_ecs::ArchetypeId>> {
-                Self::get_archetypes_read()
+    }
+}
+
+/// Implements `Serializable` by serializing the fields in order. Enums store
+/// the index of their variant first.
+pub fn derive_serializable_impl(ast: &syn::DeriveInput) -> TokenStream2 {
+    let name = &ast.ident;
+    let mut generics = ast.generics.clone();
+    let type_params: Vec<syn::Ident> = generics.type_params().map(|param| param.ident.clone()).collect();
+    let where_clause = generics.make_where_clause();
+    for param in type_params {
+        where_clause.predicates.push(syn::parse_quote! { #param: kiwi_ecs::Serializable });
+    }
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    
+    let (serialize, deserialize) = match &ast.data {
+        syn::Data::Struct(data) => {
+            let (pattern, bindings) = fields_pattern(&data.fields);
+            let construct = fields_construct(&data.fields);
+            (
+                quote! {
+                    let Self #pattern = self;
+                    #(kiwi_ecs::Serializable::serialize(#bindings, out);)*
+                },
+                quote! {
+                    return Ok(Self #construct);
+                }
+            )
+        },
+        syn::Data::Enum(data) => {
+            let serialize_arms = data.variants.iter().enumerate().map(|(i, variant)| {
+                let variant_name = &variant.ident;
+                let index = i as u32;
+                let (pattern, bindings) = fields_pattern(&variant.fields);
+                quote! {
+                    Self::#variant_name #pattern => {
+                        kiwi_ecs::Serializable::serialize(&#index, out);
+                        #(kiwi_ecs::Serializable::serialize(#bindings, out);)*
+                    }
+                }
+            });
+            let deserialize_arms = data.variants.iter().enumerate().map(|(i, variant)| {
+                let variant_name = &variant.ident;
+                let index = i as u32;
+                let construct = fields_construct(&variant.fields);
+                quote! {
+                    #index => Ok(Self::#variant_name #construct),
+                }
+            });
+            let name_str = name.to_string();
+            (
+                quote! {
+                    match self {
+                        #(#serialize_arms)*
+                    }
+                },
+                quote! {
+                    return match <u32 as kiwi_ecs::Serializable>::deserialize(input)? {
+                        #(#deserialize_arms)*
+                        _ => Err(kiwi_ecs::SerializeError::InvalidValue(#name_str)),
+                    };
+                }
+            )
+        },
+        syn::Data::Union(_) => panic!("Unions can't be serialized"),
+    };
+    
+    quote! {
+        impl #impl_generics kiwi_ecs::Serializable for #name #type_generics #where_clause {
+            #[allow(unused_variables)]
+            fn serialize(&self, out: &mut ::std::vec::Vec<u8>) {
+                #serialize
             }
-            #[inline]
-            fn add_archetype(arch_id: kiwi_ecs::ArchetypeId) where Self: Sized {
-                let mut arch_guard = Self::get_archetypes_write();
-                (*arch_guard).push(arch_id);
+            
+            #[allow(unused_variables)]
+            fn deserialize(input: &mut &[u8]) -> ::std::result::Result<Self, kiwi_ecs::SerializeError> {
+                #deserialize
             }
-            #[inline(always)]
-            fn id() -> kiwi_ecs::ComponentId where Self: Sized { #this_id }
         }
     }
 }
+
+/// A pattern binding all fields, e.g. `{ x: __field0, y: __field1 }`, and the bindings
+fn fields_pattern(fields: &syn::Fields) -> (TokenStream2, Vec<syn::Ident>) {
+    let bindings: Vec<syn::Ident> = (0..fields.len())
+        .map(|i| syn::Ident::new(&format!("__field{i}"), proc_macro2::Span::call_site()))
+        .collect();
+    let pattern = match fields {
+        syn::Fields::Named(named) => {
+            let names = named.named.iter().map(|field| field.ident.as_ref().unwrap());
+            quote! { { #(#names: #bindings,)* } }
+        },
+        syn::Fields::Unnamed(_) => quote! { (#(#bindings,)*) },
+        syn::Fields::Unit => quote! {},
+    };
+    return (pattern, bindings);
+}
+
+/// Constructs the fields by deserializing them in order
+fn fields_construct(fields: &syn::Fields) -> TokenStream2 {
+    let deserialize = quote! { kiwi_ecs::Serializable::deserialize(input)? };
+    return match fields {
+        syn::Fields::Named(named) => {
+            let names = named.named.iter().map(|field| field.ident.as_ref().unwrap());
+            quote! { { #(#names: #deserialize,)* } }
+        },
+        syn::Fields::Unnamed(unnamed) => {
+            let values = unnamed.unnamed.iter().map(|_| &deserialize);
+            quote! { (#(#values,)*) }
+        },
+        syn::Fields::Unit => quote! {},
+    };
+}
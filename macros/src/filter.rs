@@ -0,0 +1,94 @@
+use proc_macro2::{Delimiter, TokenStream as TokenStream2, TokenTree};
+use quote::quote;
+
+/// Parses a query filter: `with Type`, `without Type`, `flag Flags::Variant`,
+/// `Added<Type>`, `Changed<Type>` or `or(filter, ...)`. Returns `None` if the
+/// tokens are not a filter.
+pub(crate) fn parse_filter(tokens: TokenStream2) -> Option<TokenStream2> {
+    let mut iter = tokens.into_iter();
+    let keyword = match iter.next() {
+        Some(TokenTree::Ident(ident)) => ident.to_string(),
+        _ => return None,
+    };
+    let rest: TokenStream2 = iter.collect();
+    // e.g. `with: Type` in a system is a parameter, not a filter
+    match rest.clone().into_iter().next() {
+        None => return None,
+        Some(TokenTree::Punct(p)) if p.as_char() == ':' => return None,
+        _ => {}
+    }
+    
+    match keyword.as_str() {
+        "with" => Some(quote! { kiwi_ecs::Filter::with::<#rest>() }),
+        "without" => Some(quote! { kiwi_ecs::Filter::without::<#rest>() }),
+        "flag" => Some(quote! { kiwi_ecs::Filter::flag(#rest) }),
+        "Added" => angle_bracketed(rest).map(|ty| quote! { kiwi_ecs::Filter::added::<#ty>() }),
+        "Changed" => angle_bracketed(rest).map(|ty| quote! { kiwi_ecs::Filter::changed::<#ty>() }),
+        "or" => {
+            let mut rest = rest.into_iter();
+            let group = match (rest.next(), rest.next()) {
+                (Some(TokenTree::Group(group)), None) if group.delimiter() == Delimiter::Parenthesis => group,
+                _ => panic!("Expected a list of filters in parentheses after `or`, e.g. `or(with A, flag Flags::B)`"),
+            };
+            let filters: Vec<TokenStream2> = split_commas(group.stream()).into_iter()
+                .map(|filter| {
+                    parse_filter(filter.clone())
+                        .unwrap_or_else(|| panic!("`{}` is not a filter. Filters are `with Type`, `without Type`, `flag Flag`, `Added<Type>`, `Changed<Type>` or `or(...)`", filter))
+                })
+                .collect();
+            Some(quote! { kiwi_ecs::Filter::or(vec![#(#filters,)*]) })
+        }
+        _ => None
+    }
+}
+
+/// The type between `<` and `>`, e.g. `Pos` for `<Pos>`
+fn angle_bracketed(tokens: TokenStream2) -> Option<TokenStream2> {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    match (tokens.first(), tokens.last()) {
+        (Some(TokenTree::Punct(open)), Some(TokenTree::Punct(close)))
+            if tokens.len() > 2 && open.as_char() == '<' && close.as_char() == '>' => {
+            Some(tokens[1..tokens.len() - 1].iter().cloned().collect())
+        }
+        _ => None
+    }
+}
+
+/// The component types of the `Added` and `Changed` filters in a filter, as
+/// these read the change ticks of the component
+pub(crate) fn tracked_components(tokens: TokenStream2) -> Vec<TokenStream2> {
+    let mut iter = tokens.into_iter();
+    let keyword = match iter.next() {
+        Some(TokenTree::Ident(ident)) => ident.to_string(),
+        _ => return Vec::new(),
+    };
+    let rest: TokenStream2 = iter.collect();
+    match keyword.as_str() {
+        "Added" | "Changed" => angle_bracketed(rest).into_iter().collect(),
+        "or" => match rest.into_iter().next() {
+            Some(TokenTree::Group(group)) => split_commas(group.stream()).into_iter()
+                .flat_map(tracked_components)
+                .collect(),
+            _ => Vec::new(),
+        },
+        _ => Vec::new()
+    }
+}
+
+/// Splits tokens on the commas that are not inside a group. A trailing comma is ignored.
+pub(crate) fn split_commas(tokens: TokenStream2) -> Vec<TokenStream2> {
+    let mut parts = Vec::new();
+    let mut current: Vec<TokenTree> = Vec::new();
+    for tt in tokens {
+        match &tt {
+            TokenTree::Punct(p) if p.as_char() == ',' => {
+                parts.push(current.drain(..).collect());
+            }
+            _ => current.push(tt),
+        }
+    }
+    if !current.is_empty() {
+        parts.push(current.into_iter().collect());
+    }
+    return parts;
+}
@@ -5,6 +5,7 @@ mod query;
 mod component;
 mod entity;
 mod flags;
+mod filter;
 
 //======================
 // System
@@ -23,6 +24,7 @@ pub fn system(attr: TokenStream, item: TokenStream) -> TokenStream {
 /// Takes the following parameters:
 /// - world: &World
 /// - ...components: ...Component
+/// - ...filters: `with Type`, `without Type`, `flag Flag` or `or(...filters)`
 pub fn query(item: TokenStream) -> TokenStream {
     crate::query::gen_query_tokens(item, "query")
 }
@@ -31,6 +33,7 @@ pub fn query(item: TokenStream) -> TokenStream {
 /// Takes the following parameters:
 /// - world: &mut World
 /// - ...components: ...Component
+/// - ...filters: `with Type`, `without Type`, `flag Flag` or `or(...filters)`
 pub fn query_mut(item: TokenStream) -> TokenStream {
     crate::query::gen_query_tokens(item, "query_mut")
 }
@@ -56,7 +59,11 @@ pub fn spawn_entity(item: TokenStream) -> TokenStream {
 // Component
 //======================
 
-#[proc_macro_derive(Component)]
+/// `#[component(serialize)]` also implements `Serializable` and registers the
+/// component to be saved with the world, under its module path and type name or
+/// under the name given by `#[component(serialize, name = "...")]`. `#[component(clone)]` allows
+/// cloning the component with the world, which requires the component to be `Clone`.
+#[proc_macro_derive(Component, attributes(component))]
 pub fn derive_component(item: TokenStream) -> TokenStream {
     let ast: syn::DeriveInput = syn::parse(item).unwrap();
     let field_less = match &ast.data {
@@ -73,10 +80,24 @@ pub fn derive_component(item: TokenStream) -> TokenStream {
     
     let name = &ast.ident;
     let generics_and_lifetimes = &ast.generics;
+    let attrs = crate::component::parse_component_attrs(&ast);
     
-    let ts = TokenStream::from(crate::component::derive_component_impl(name, generics_and_lifetimes));
-    
-    ts
+    let component = crate::component::derive_component_impl(name, generics_and_lifetimes, &attrs);
+    let serializable = if attrs.serialize {
+        crate::component::derive_serializable_impl(&ast)
+    } else {
+        quote::quote! {}
+    };
+    TokenStream::from(quote::quote! {
+        #component
+        #serializable
+    })
+}
+
+#[proc_macro_derive(Serializable)]
+pub fn derive_serializable(item: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = syn::parse(item).unwrap();
+    TokenStream::from(crate::component::derive_serializable_impl(&ast))
 }
 
 #[proc_macro_attribute]
@@ -2,21 +2,14 @@ use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
 
-// TODO: allow trailing comma in query macro
+use crate::filter::{parse_filter, split_commas};
+
 pub fn gen_query_tokens(item: TokenStream, func_name: &str) -> TokenStream {
-    let item_iter = item.into_iter();
-    let mut item_collect = ItemCollect { item: item_iter, collected: Vec::new() };
-    let mut is_end = false;
-    let world: String = match item_collect.collect_next() {
-        ItemCollectResult::ContainsMore(s) => s,
-        ItemCollectResult::End(s) => {
-            is_end = true;
-            s
-        }
-    };
-    let world: &TokenStream2 = &world.parse().unwrap();
+    let mut args = split_commas(TokenStream2::from(item)).into_iter();
+    let world: TokenStream2 = args.next()
+        .expect("Expected the world as the first parameter of the query");
     
-    if is_end {
+    if args.len() == 0 {
         let func_name: TokenStream2 = format!("{func_name}0").parse().unwrap();
         return TokenStream::from(quote! {
             (#world).#func_name()
@@ -24,78 +17,119 @@ pub fn gen_query_tokens(item: TokenStream, func_name: &str) -> TokenStream {
     }
     
     let mut components: Vec<TokenStream2> = Vec::new();
-    loop {
-        match item_collect.collect_next() {
-            ItemCollectResult::ContainsMore(s) => {
-                components.push(s.parse().unwrap());
-            }
-            ItemCollectResult::End(s) => {
-                components.push(s.parse().unwrap());
-                break;
-            }
+    let mut filters: Vec<TokenStream2> = Vec::new();
+    for arg in args {
+        match parse_filter(arg.clone()) {
+            Some(filter) => filters.push(filter),
+            None => components.push(arg),
         }
     }
     
     // TODO: make a better entity id check
-    let query_id = components[0].to_string() == "EntityId".to_string();
+    let query_id = !components.is_empty() && components[0].to_string() == "EntityId";
+    
+    if query_id {
+        components.remove(0);
+    }
+    let components: Vec<QueryType> = components.into_iter().map(parse_query_type).collect();
     
-    let components = if query_id {
-        &components[1..components.len()]
+    // `&mut T` needs a mutable query
+    let func_name = if components.iter().any(|ty| ty.access == Some(true)) {
+        "query_mut"
     } else {
-        &components
+        func_name
     };
+    if func_name == "query_mut" {
+        check_duplicate_components(&components);
+    }
+    let components: Vec<&TokenStream2> = components.iter().map(|ty| &ty.tokens).collect();
+    
+    let func_name: TokenStream2 = format!(
+        "{func_name}{}{}{}",
+        if filters.is_empty() { "" } else { "_filtered" },
+        if query_id { "_ids" } else { "" },
+        components.len()
+    ).parse().unwrap();
     
-    let func_name: TokenStream2 = format!("{func_name}{}{}", if query_id { "_ids" } else { "" }, components.len()).parse().unwrap();
+    let generics = if components.is_empty() {
+        quote! {}
+    } else {
+        quote! { ::<#(#components,)*> }
+    };
+    
+    let args = if filters.is_empty() {
+        quote! {}
+    } else {
+        quote! { &[#(#filters,)*] }
+    };
     
     TokenStream::from(quote! {
-        (#world).#func_name::<#(#components,)*>()
+        (#world).#func_name #generics (#args)
     })
 }
 
-enum ItemCollectResult {
-    ContainsMore(String),
-    End(String)
-}
-
-struct ItemCollect {
-    item: proc_macro::token_stream::IntoIter,
-    collected: Vec<proc_macro::TokenTree>
+/// A component type in a query
+pub(crate) struct QueryType {
+    /// The type passed to the query functions. `&T` and `&mut T` are replaced
+    /// by `Read<T>` and `Write<T>`
+    pub(crate) tokens: TokenStream2,
+    /// The component type, without references or `Option`
+    pub(crate) component: String,
+    /// `Some(true)` for `&mut T`, `Some(false)` for `&T` and `None` for `T`
+    pub(crate) access: Option<bool>,
 }
 
-impl ItemCollect {
-    fn collect_next(&mut self) -> ItemCollectResult {
-        loop {
-            let next = self.item.next();
-            match next {
-                Some(tt) => {
-                    match &tt {
-                        proc_macro::TokenTree::Group(_) |
-                        proc_macro::TokenTree::Ident(_) |
-                        proc_macro::TokenTree::Literal(_) 
-                            => self.collected.push(tt),
-                        proc_macro::TokenTree::Punct(p) => {
-                            if p.as_char() == ',' {
-                                let collected = self.collected.clone();
-                                self.collected.clear();
-                                return ItemCollectResult::ContainsMore(
-                                    collected.iter().map(|tt| {
-                                        tt.to_string()
-                                    }).collect::<String>()
-                                );
-                            } else {
-                                self.collected.push(tt);
-                            }
-                        },
+pub(crate) fn parse_query_type(ty: TokenStream2) -> QueryType {
+    match syn::parse2::<syn::Type>(ty.clone()) {
+        Ok(syn::Type::Reference(reference)) => {
+            let elem = &reference.elem;
+            let writes = reference.mutability.is_some();
+            return QueryType {
+                tokens: if writes {
+                    quote! { kiwi_ecs::Write<#elem> }
+                } else {
+                    quote! { kiwi_ecs::Read<#elem> }
+                },
+                component: quote!(#elem).to_string(),
+                access: Some(writes),
+            };
+        }
+        Ok(syn::Type::Path(path)) => {
+            if let Some(segment) = path.path.segments.last() {
+                if segment.ident == "Option" {
+                    if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
+                        if let Some(syn::GenericArgument::Type(inner)) = args.args.first() {
+                            let inner = parse_query_type(quote!(#inner));
+                            let inner_tokens = &inner.tokens;
+                            return QueryType {
+                                tokens: quote! { Option<#inner_tokens> },
+                                ..inner
+                            };
+                        }
                     }
                 }
-                None => {
-                    return ItemCollectResult::End(
-                        self.collected.iter().map(|tt| {
-                                tt.to_string()
-                        }).collect::<String>()
-                    );
-                }
             }
         }
+        _ => {}
+    }
+    return QueryType {
+        component: ty.to_string(),
+        tokens: ty,
+        access: None,
+    };
+}
+
+/// Panics if a component that is written to occurs more than once in a mutable
+/// query, which would hand out a mutable reference and another reference to
+/// the same component
+pub(crate) fn check_duplicate_components(components: &[QueryType]) {
+    // components without `&` or `&mut` are mutable in mutable queries
+    let writes = |ty: &QueryType| ty.access.unwrap_or(true);
+    for (i, ty) in components.iter().enumerate() {
+        let conflict = components[..i].iter()
+            .any(|other| other.component == ty.component && (writes(ty) || writes(other)));
+        if conflict {
+            panic!("Component `{}` is used more than once in a mutable query. This would create multiple references to the same component of which one is mutable.", ty.component);
+        }
     }
 }
@@ -1,7 +1,10 @@
 use proc_macro::TokenStream;
-// use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
 
+use crate::filter::{parse_filter, split_commas, tracked_components};
+use crate::query::{QueryType, parse_query_type, check_duplicate_components};
+
 pub fn system_macro(attr: TokenStream, item: TokenStream) -> TokenStream {
     if item.is_empty() {
         panic!("The system attribute macro can only be applied to functions");
@@ -40,6 +43,12 @@ pub fn system_macro(attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut param_vars = Vec::new();
     // component types
     let mut param_types = Vec::new();
+    let mut filters = Vec::new();
+    // components of which the change ticks are read by filters
+    let mut tracked = Vec::new();
+    let mut resources: Vec<Resource> = Vec::new();
+    let mut events: Vec<Resource> = Vec::new();
+    let mut commands: Option<syn::Ident> = None;
     let mut entity_id: bool = false;
     
     #[cfg(feature = "try")]
@@ -48,19 +57,34 @@ pub fn system_macro(attr: TokenStream, item: TokenStream) -> TokenStream {
     for attr in attrs {
         match attr {
             ParamType::Param(param) => {
-                let name = syn::Ident::new(&param.var_name.to_string(), proc_macro2::Span::call_site());
-                let ty = syn::Ident::new(&param.var_type.to_string(), proc_macro2::Span::call_site());
-                
-                param_vars.push(name);
-                param_types.push(ty.clone());
+                param_vars.push(param.var_name);
+                param_types.push(param.var_type);
             },
             ParamType::EntityId(ident) => {
-                let name = syn::Ident::new(&ident.var_name.to_string(), proc_macro2::Span::call_site());
-                let mut new = vec![name];
+                let mut new = vec![ident.var_name];
                 new.append(&mut param_vars);
                 param_vars = new; // id is always first variable in query result
                 entity_id = true;
             },
+            ParamType::Filter { filter, mut components } => {
+                filters.push(filter);
+                tracked.append(&mut components);
+            },
+            ParamType::Resource(resource) => {
+                resources.push(resource);
+            },
+            ParamType::Events(event) => {
+                events.push(event);
+            },
+            ParamType::Commands(name) => {
+                if !is_world_mutable {
+                    panic!("Commands can only be used in a system with a mutable world, as they are applied at the end of the system. Use `world: &mut World` instead.");
+                }
+                if commands.is_some() {
+                    panic!("A system can only have one command buffer");
+                }
+                commands = Some(name);
+            },
             ParamType::Try => {
                 #[cfg(feature = "try")]
                 { try_enabled = true; }
@@ -70,25 +94,40 @@ pub fn system_macro(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     }
     
+    let query_types: Vec<QueryType> = param_types.into_iter().map(parse_query_type).collect();
+    if is_world_mutable {
+        check_duplicate_components(&query_types);
+    } else if let Some(ty) = query_types.iter().find(|ty| ty.access == Some(true)) {
+        panic!("Component `{}` is written to in a system with an immutable world. Use `world: &mut World` instead.", ty.component);
+    }
+    let param_types: Vec<TokenStream2> = query_types.iter().map(|ty| ty.tokens.clone()).collect();
+    
     let count = param_types.len();
+    let filtered = if filters.is_empty() { "" } else { "_filtered" };
      
     let query_func = match is_world_mutable {
         true => {
-            if entity_id == true {
-                syn::Ident::new(&format!("query_mut_ids{count}"), proc_macro2::Span::call_site())
+            if entity_id {
+                syn::Ident::new(&format!("query_mut{filtered}_ids{count}"), proc_macro2::Span::call_site())
             } else {
-                syn::Ident::new(&format!("query_mut{count}"), proc_macro2::Span::call_site())
+                syn::Ident::new(&format!("query_mut{filtered}{count}"), proc_macro2::Span::call_site())
             }
         }
         false => {
-            if entity_id == true {
-                syn::Ident::new(&format!("query_ids{count}"), proc_macro2::Span::call_site())
+            if entity_id {
+                syn::Ident::new(&format!("query{filtered}_ids{count}"), proc_macro2::Span::call_site())
             } else {
-                syn::Ident::new(&format!("query{count}"), proc_macro2::Span::call_site())
+                syn::Ident::new(&format!("query{filtered}{count}"), proc_macro2::Span::call_site())
             }
         }
     };
     
+    let query_args = if filters.is_empty() {
+        quote! {}
+    } else {
+        quote! { &[#(#filters,)*] }
+    };
+    
     let for_each_parameter = if param_vars.len() == 1 {
         let param_var = &param_vars[0];
         quote! {
@@ -111,16 +150,16 @@ pub fn system_macro(attr: TokenStream, item: TokenStream) -> TokenStream {
     // };
     
     #[cfg(feature = "try")]
-    let mut query_def = quote! { let __query = #world_name_ident.#query_func ::<#(#param_types,)*>(); };
+    let mut query_def = quote! { let __query = #world_name_ident.#query_func ::<#(#param_types,)*>(#query_args); };
     #[cfg(not(feature = "try"))]
-    let query_def = quote! { let __query = #world_name_ident.#query_func ::<#(#param_types,)*>(); };
+    let query_def = quote! { let __query = #world_name_ident.#query_func ::<#(#param_types,)*>(#query_args); };
 
     let query = if sys_sig.output != syn::ReturnType::Default {
         // function has Result return type
         #[cfg(feature = "try")]
         {
             query_def = quote! {
-                let mut __query = #world_name_ident.#query_func ::<#(#param_types,)*>();
+                let mut __query = #world_name_ident.#query_func ::<#(#param_types,)*>(#query_args);
             };
             
             if try_enabled {
@@ -160,16 +199,187 @@ pub fn system_macro(attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
     
-    let body = quote! {
-        #query_def
-        #query
+    // resources are accessed through pointers, because the world is borrowed by the query
+    for (i, resource) in resources.iter().enumerate() {
+        if resource.mutable && !is_world_mutable {
+            panic!("Resource `{}` is written to in a system with an immutable world. Use `world: &mut World` instead.", resource.var_type);
+        }
+        let conflict = resources[..i].iter()
+            .any(|other| other.var_type.to_string() == resource.var_type.to_string() && (resource.mutable || other.mutable));
+        if conflict {
+            panic!("Resource `{}` is used more than once in a system, while one of them is mutable.", resource.var_type);
+        }
+    }
+    for (i, event) in events.iter().enumerate() {
+        if event.mutable && !is_world_mutable {
+            panic!("Event `{}` is sent in a system with an immutable world. Use `world: &mut World` instead.", event.var_type);
+        }
+        let conflict = events[..i].iter()
+            .any(|other| other.var_type.to_string() == event.var_type.to_string() && (event.mutable || other.mutable));
+        if conflict {
+            panic!("Event `{}` is used more than once in a system, while one of them sends events.", event.var_type);
+        }
+    }
+    let resource_defs: Vec<TokenStream2> = resources.iter().map(|resource| {
+        let name = &resource.var_name;
+        let ty = &resource.var_type;
+        if resource.mutable {
+            quote! { let #name: &mut #ty = unsafe { &mut *#world_name_ident.resource_ptr::<#ty>() }; }
+        } else {
+            quote! { let #name: &#ty = unsafe { &*#world_name_ident.resource_ptr::<#ty>() }; }
+        }
+    }).collect();
+    // events are read since the last run of the system, which is passed to the parallel function
+    let event_defs = |last_change_tick: TokenStream2| -> Vec<TokenStream2> {
+        events.iter().map(|event| {
+            let name = &event.var_name;
+            let ty = &event.var_type;
+            if event.mutable {
+                quote! {
+                    #[allow(unused_mut)]
+                    let mut #name: kiwi_ecs::EventWriter<'_, #ty> = kiwi_ecs::EventWriter::new(
+                        #world_name_ident.events_ptr::<#ty>().map(|events| unsafe { &mut *events }),
+                        #world_name_ident.change_tick()
+                    );
+                }
+            } else {
+                quote! {
+                    let #name: kiwi_ecs::EventReader<'_, #ty> = kiwi_ecs::EventReader::new(
+                        #world_name_ident.events_ptr::<#ty>().map(|events| unsafe { &*events }),
+                        #last_change_tick
+                    );
+                }
+            }
+        }).collect()
+    };
+    let system_event_defs = event_defs(quote! { #world_name_ident.last_change_tick() });
+    
+    let info = if inputs.len() == 1 && sys_sig.output == syn::ReturnType::Default && sys_sig.generics.params.is_empty() {
+        let sys_name = &sys_sig.ident;
+        let info_name = syn::Ident::new(&format!("{}_info", sys_name), sys_name.span());
+        let info_doc = format!("The access of the system `{}`, used to run it in parallel with other systems", sys_name);
+        
+        // components without `&` or `&mut` are written to in systems with a mutable world
+        let access: Vec<TokenStream2> = query_types.iter().map(|ty| {
+            let component: TokenStream2 = ty.component.parse().unwrap();
+            if is_world_mutable && ty.access.unwrap_or(true) {
+                quote! { .write::<#component>() }
+            } else {
+                quote! { .read::<#component>() }
+            }
+        }).chain(resources.iter().map(|resource| {
+            let ty = &resource.var_type;
+            if resource.mutable {
+                quote! { .write_resource::<#ty>() }
+            } else {
+                quote! { .read_resource::<#ty>() }
+            }
+        })).chain(events.iter().map(|event| {
+            let ty = &event.var_type;
+            if event.mutable {
+                quote! { .write_resource::<kiwi_ecs::Events<#ty>>() }
+            } else {
+                quote! { .read_resource::<kiwi_ecs::Events<#ty>>() }
+            }
+        })).chain(tracked.iter().map(|component| {
+            quote! { .read::<#component>() }
+        })).collect();
+        let accessed_types: Vec<TokenStream2> = query_types.iter()
+            .map(|ty| ty.component.parse().unwrap())
+            .chain(resources.iter().map(|resource| resource.var_type.clone()))
+            .chain(events.iter().map(|event| {
+                let ty = &event.var_type;
+                quote! { kiwi_ecs::Events<#ty> }
+            }))
+            .collect();
+        
+        // only systems with a mutable world can run in parallel, because the
+        // world can't be used in their body while the query borrows it. Command
+        // buffers are applied to the world, so those systems can't either.
+        let parallel = if is_world_mutable && commands.is_none() {
+            let unchecked_args = if filters.is_empty() {
+                quote! {}
+            } else {
+                quote! { &[#(#filters,)*], __last_change_tick }
+            };
+            let unchecked_func = syn::Ident::new(
+                &format!("query_mut_unchecked{filtered}{}{count}", if entity_id { "_ids" } else { "" }),
+                proc_macro2::Span::call_site()
+            );
+            let parallel_event_defs = event_defs(quote! { __last_change_tick });
+            quote! {
+                unsafe fn __kiwi_parallel(#world_name_ident: &kiwi_ecs::World, __last_change_tick: u32) {
+                    #(#resource_defs)*
+                    #(#parallel_event_defs)*
+                    let __query = #world_name_ident.#unchecked_func ::<#(#param_types,)*>(#unchecked_args);
+                    __query.for_each(|#for_each_parameter| {
+                        #func_body
+                    });
+                }
+                let parallel: Option<unsafe fn(&kiwi_ecs::World, u32)> = if send_sync {
+                    Some(__kiwi_parallel)
+                } else {
+                    None
+                };
+            }
+        } else {
+            quote! { let parallel = None; }
+        };
+        
+        quote! {
+            #[doc = #info_doc]
+            #[allow(dead_code)]
+            #sys_vis fn #info_name() -> kiwi_ecs::SystemInfo {
+                // systems accessing components or resources that aren't `Send` and `Sync` are exclusive
+                let send_sync = {
+                    use kiwi_ecs::{IsSendSync as _, NotSendSync as _};
+                    let send_sync: &[bool] = &[#((&kiwi_ecs::SendSyncCheck::<#accessed_types>(::std::marker::PhantomData)).is_send_sync(),)*];
+                    send_sync.iter().all(|is| *is)
+                };
+                #parallel
+                let mut access = kiwi_ecs::SystemAccess::new()#(#access)*;
+                if !send_sync {
+                    access = access.exclusive();
+                }
+                // the parallel function only accesses the components and resources of the system
+                unsafe {
+                    kiwi_ecs::SystemInfo::new(stringify!(#sys_name), access, |world: &mut kiwi_ecs::World| #sys_name(world), parallel)
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    
+    let body = match commands {
+        Some(commands) => quote! {
+            let mut #commands = kiwi_ecs::Commands::new(#world_name_ident);
+            #(#resource_defs)*
+            #(#system_event_defs)*
+            #[allow(clippy::let_unit_value)]
+            let __result = {
+                #query_def
+                #query
+            };
+            // the query no longer borrows the world
+            #world_name_ident.apply_commands(&mut #commands);
+            __result
+        },
+        None => quote! {
+            #(#resource_defs)*
+            #(#system_event_defs)*
+            #query_def
+            #query
+        }
     };
     
     let ts = quote! {
         #(#sys_attr)*
         #sys_vis #sys_sig {
             #body
-        }  
+        }
+        
+        #info
     };
     
     TokenStream::from(ts)
@@ -184,18 +394,14 @@ fn system_get_world_name(inputs: &syn::punctuated::Punctuated<syn::FnArg, syn::t
         let ty = typed_param.ty.clone();
         let ty = *ty;
         if let syn::Type::Reference(ref_type) = ty {
-            if let Some(_) = ref_type.mutability {
-                world_is_mutable = true;
-            } else {
-                world_is_mutable = false;
-            }
+            world_is_mutable = ref_type.mutability.is_some();
             let elem = ref_type.elem;
             let elem = *elem;
             if let syn::Type::Path(path) = elem {
                 let path = path.path;
                 let segments = path.segments;
                 let ident = &segments.last().unwrap().ident;
-                if ident.to_string() == "World" {
+                if ident == "World" {
                     // Parameter is World
                     
                     let pat = typed_param.pat.clone();
@@ -217,46 +423,111 @@ enum ParamType {
     Param(Param),
     /// contains the name of the EntityId parameter
     EntityId(Param),
+    /// A query filter, e.g. `without Frozen`, and the components of its
+    /// `Added` and `Changed` filters
+    Filter { filter: TokenStream2, components: Vec<TokenStream2> },
+    /// A resource, e.g. `res dt: DeltaTime`
+    Resource(Resource),
+    /// Events that are read or sent, e.g. `events collisions: Collision`
+    Events(Resource),
+    /// A command buffer that is applied at the end of the system, e.g. `commands cmds`
+    Commands(syn::Ident),
     Try
 }
 
 #[derive(Debug)]
 struct Param {
-    var_name: proc_macro::Ident,
-    var_type: proc_macro::Ident,
+    var_name: syn::Ident,
+    var_type: TokenStream2,
+}
+
+#[derive(Debug)]
+struct Resource {
+    var_name: syn::Ident,
+    var_type: TokenStream2,
+    /// `res mut name: Type` or `events mut name: Type`
+    mutable: bool,
 }
 
 fn parse_system_attr(attrs: TokenStream) -> Vec<ParamType> {
-    let mut cur_var_name: Option<proc_macro::Ident> = None;
-    // let cur_var_type: Option<syn::Ident> = None;
-    
-    let mut param: Vec<ParamType> = Vec::new();
-    
-    for attr in attrs {
-        if let proc_macro::TokenTree::Ident(ident) = attr {
-            if (&cur_var_name).is_none() {
-                if ident.to_string() == String::from("try") {
-                    param.push(ParamType::Try);
-                } else {
-                    cur_var_name = Some(ident);
-                }
+    split_commas(TokenStream2::from(attrs)).into_iter()
+        .map(|attr| {
+            if attr.to_string() == "try" {
+                return ParamType::Try;
+            }
+            if let Some(filter) = parse_filter(attr.clone()) {
+                return ParamType::Filter { filter, components: tracked_components(attr) };
+            }
+            if let Some(resource) = parse_resource(attr.clone(), "res") {
+                return ParamType::Resource(resource);
+            }
+            if let Some(event) = parse_resource(attr.clone(), "events") {
+                return ParamType::Events(event);
+            }
+            if let Some(commands) = parse_commands(attr.clone()) {
+                return ParamType::Commands(commands);
+            }
+            
+            // name: Type
+            let mut tokens = attr.clone().into_iter();
+            let var_name = match tokens.next() {
+                Some(proc_macro2::TokenTree::Ident(ident)) => ident,
+                _ => panic!("Expected a parameter of the form `name: Type` in system, found `{}`", attr),
+            };
+            match tokens.next() {
+                Some(proc_macro2::TokenTree::Punct(p)) if p.as_char() == ':' => {},
+                _ => panic!("Expected a parameter of the form `name: Type` in system, found `{}`", attr),
+            }
+            let var_type: TokenStream2 = tokens.collect();
+            
+            if var_type.to_string() == "EntityId" {
+                return ParamType::EntityId(Param { var_name, var_type });
             } else {
-                if ident.to_string() == "EntityId" {
-                    param.push(ParamType::EntityId(Param {
-                        var_name: cur_var_name.as_ref().unwrap().clone(),
-                        var_type: ident
-                    }));
-                    cur_var_name = None;
-                } else {
-                    param.push(ParamType::Param(Param {
-                        var_name: cur_var_name.as_ref().unwrap().clone(),
-                        var_type: ident
-                    }));
-                    cur_var_name = None;
-                }
+                return ParamType::Param(Param { var_name, var_type });
             }
-        }
+        })
+        .collect()
+}
+
+/// Parses `<keyword> name: Type` or `<keyword> mut name: Type`, where the
+/// keyword is `res` for resources and `events` for events
+fn parse_resource(attr: TokenStream2, keyword: &str) -> Option<Resource> {
+    let mut tokens = attr.clone().into_iter().peekable();
+    match tokens.next() {
+        Some(proc_macro2::TokenTree::Ident(ident)) if ident == keyword => {},
+        _ => return None,
+    }
+    let mutable = match tokens.peek() {
+        Some(proc_macro2::TokenTree::Ident(ident)) if ident == "mut" => {
+            tokens.next();
+            true
+        },
+        // `res: Type` is a component named `res`, the same goes for `events`
+        Some(proc_macro2::TokenTree::Ident(_)) => false,
+        _ => return None,
+    };
+    let var_name = match tokens.next() {
+        Some(proc_macro2::TokenTree::Ident(ident)) => ident,
+        _ => panic!("Expected a parameter of the form `{} name: Type` in system, found `{}`", keyword, attr),
+    };
+    match tokens.next() {
+        Some(proc_macro2::TokenTree::Punct(p)) if p.as_char() == ':' => {},
+        _ => panic!("Expected a parameter of the form `{} name: Type` in system, found `{}`", keyword, attr),
     }
+    let var_type: TokenStream2 = tokens.collect();
     
-    return param;
+    return Some(Resource { var_name, var_type, mutable });
+}
+
+/// Parses `commands name`
+fn parse_commands(attr: TokenStream2) -> Option<syn::Ident> {
+    let mut tokens = attr.into_iter();
+    match (tokens.next(), tokens.next(), tokens.next()) {
+        (
+            Some(proc_macro2::TokenTree::Ident(keyword)),
+            Some(proc_macro2::TokenTree::Ident(name)),
+            None
+        ) if keyword == "commands" => Some(name),
+        _ => None
+    }
 }
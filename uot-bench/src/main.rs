@@ -120,8 +120,11 @@ unsafe fn check_collisions(world: &mut World, collision_limit: u32, death_count:
     
     (*query_ptr).iter_mut().for_each(|(id1, pos1, col1)| {
         query.iter().for_each(|(id2, pos2, col2)| {
-            if id1 != id2 && !dead.contains(&id1) && !dead.contains(&id2) {
+            if id1 != id2 && !dead.contains(id1) && !dead.contains(id2) {
                 let dx = pos1.x - pos2.x;
+                // `pos2.y - pos2.y` is 0, but is kept as in the original benchmark,
+                // so that it measures the same amount of collisions as before
+                #[allow(clippy::eq_op)]
                 let dy = pos2.y - pos2.y;
                 let dist_sq = (dx * dx) + (dy * dy);
                 
@@ -129,7 +132,7 @@ unsafe fn check_collisions(world: &mut World, collision_limit: u32, death_count:
                 let dr_sq = dr * dr;
                 
                 if dr_sq > dist_sq {
-                    (*col1).count += 1;
+                    col1.count += 1;
                 }
                 
                 // kill and spawn one